@@ -0,0 +1,1659 @@
+//! Command line and config file handling.
+//!
+//! Every option can also be set from `$XDG_CONFIG_HOME/glpaper/config.toml` (or the file given
+//! with `--config`), using the long flag name with dashes replaced by underscores. Values given on
+//! the command line win over the file. `--uniform` values go in a `[uniform]` table and per-output
+//! settings go in `[[output]]` tables:
+//!
+//! ```toml
+//! fps = 30
+//!
+//! [uniform]
+//! exposure = 1.5
+//!
+//! [[output]]
+//! name = "DP-1"
+//! shader = "/home/me/shaders/plasma.wgsl"
+//! present_mode = "fifo"
+//!
+//! [[output]]
+//! model = "DELL U2720Q"
+//! fps = 30
+//! ```
+//!
+//! An `[[output]]` table picks its output by `name` (the connector, e.g. `DP-1`), `make`, `model`
+//! or part of the `description`. Connector names can change between sessions on some
+//! compositors, the others follow the monitor. When several tables match an output, the one
+//! picking by name wins, then description, model and make, since more outputs can share those.
+
+use std::{
+    collections::BTreeMap,
+    env, fs,
+    path::{Path, PathBuf},
+    time::{Duration, Instant},
+};
+
+use anyhow::{bail, Context, Result};
+use clap::{Parser, Subcommand, ValueEnum};
+use sctk::{
+    output::OutputInfo,
+    shell::wlr_layer::{Anchor, KeyboardInteractivity, Layer},
+};
+use serde::{Deserialize, Deserializer};
+
+use crate::renderer::adapter::AdapterChoice;
+
+const DEFAULT_FPS: u32 = 60;
+
+#[derive(Parser, Deserialize, Debug, Default)]
+#[command(version, about = "Shader wallpapers for wlr-layer-shell compositors")]
+#[serde(default, deny_unknown_fields)]
+pub struct Options {
+    /// WGSL file defining `main_image`, or the name of a bundled example, rendered on every output
+    #[arg(short, long)]
+    pub shader: Option<PathBuf>,
+
+    /// Another shader drawn over `--shader`, blended with `alpha` (the default), `add` or
+    /// `screen`, e.g. `screen:stars.wgsl` (repeatable, drawn in order)
+    #[arg(long = "overlay", value_name = "[BLEND:]SHADER", value_parser = parse_overlay)]
+    #[serde(rename = "overlay")]
+    pub overlays: Option<Vec<Overlay>>,
+
+    /// Frames per second to render at. 0 draws a frame whenever the compositor asks for one
+    /// instead, which follows the output's refresh rate and stops while it's covered
+    #[arg(short, long)]
+    pub fps: Option<u32>,
+
+    /// Presentation mode for the wgpu surface
+    #[arg(long, value_enum)]
+    pub present_mode: Option<PresentMode>,
+
+    /// Multiply the shader's clock by this, e.g. 0.5 for half speed
+    #[arg(long, value_name = "FACTOR")]
+    pub time_scale: Option<f32>,
+
+    /// Run every output's shader on the same clock, so animations line up across monitors
+    #[arg(long, overrides_with = "no_sync_outputs")]
+    pub sync_outputs: bool,
+
+    /// Turn off `--sync-outputs` when the config file turns it on
+    #[arg(long, overrides_with = "sync_outputs")]
+    #[serde(skip)]
+    pub no_sync_outputs: bool,
+
+    /// Draw the shader once, on the first output, and show that frame on every other output,
+    /// stretched to fit. Saves drawing it on each. Only the first output's settings are used, and
+    /// if the outputs can't share a gpu each is drawn on its own
+    #[arg(long, overrides_with = "no_mirror")]
+    pub mirror: bool,
+
+    /// Turn off `--mirror` when the config file turns it on
+    #[arg(long, overrides_with = "mirror")]
+    #[serde(skip)]
+    pub no_mirror: bool,
+
+    /// Value of the shader's `seed` uniform, for a generated look that can be had again, up to
+    /// 16777216 so it's exact as an `f32`. Picked at random for each run otherwise, and logged
+    #[arg(long, value_parser = parse_seed)]
+    pub seed: Option<Seed>,
+
+    /// Only draw on this output, leaving the others alone. Given by connector name, or as
+    /// `make=`, `model=` or `description=` (repeatable)
+    #[arg(long, value_name = "[FIELD=]VALUE", value_parser = parse_output_selector)]
+    pub output_only: Option<Vec<OutputSelector>>,
+
+    /// Don't draw on this output, given like `--output-only` (repeatable)
+    #[arg(long, value_name = "[FIELD=]VALUE", value_parser = parse_output_selector)]
+    pub exclude_output: Option<Vec<OutputSelector>>,
+
+    /// Render at this fraction of the output's size and stretch the result to fit, e.g. 0.5
+    #[arg(long, value_name = "FACTOR", value_parser = parse_render_scale)]
+    pub render_scale: Option<f32>,
+
+    /// With `--render-scale`, report the output's full size in `resolution` rather than the size
+    /// actually rendered at
+    #[arg(long, overrides_with = "no_report_full_resolution")]
+    pub report_full_resolution: bool,
+
+    /// Turn off `--report-full-resolution` when the config file turns it on
+    #[arg(long, overrides_with = "report_full_resolution")]
+    #[serde(skip)]
+    pub no_report_full_resolution: bool,
+
+    /// Samples per pixel for multisample antialiasing, one of 1, 2, 4 or 8
+    #[arg(long, value_name = "SAMPLES", value_parser = parse_msaa)]
+    pub msaa: Option<u32>,
+
+    /// Let the shader read the last frame it drew with `previous_frame(uv)`
+    #[arg(long, overrides_with = "no_feedback")]
+    pub feedback: bool,
+
+    /// Turn off `--feedback` when the config file turns it on
+    #[arg(long, overrides_with = "feedback")]
+    #[serde(skip)]
+    pub no_feedback: bool,
+
+    /// WGSL file defining `precompute(coord, size)`, run as a compute shader once per pixel
+    /// before the first frame and on every resize. The shader reads what it returned with
+    /// `precomputed(uv)`
+    #[arg(long, value_name = "SHADER")]
+    pub compute: Option<PathBuf>,
+
+    /// Surface format to present in, instead of the first sRGB one available. See `--dump-caps`
+    /// for what an output supports
+    #[arg(long, value_enum)]
+    pub format: Option<SurfaceFormat>,
+
+    /// How the picture is smoothed when `--render-scale` stretches it over the surface.
+    /// Nearest keeps the pixels sharp, for a deliberately low resolution look
+    #[arg(long, value_enum)]
+    pub upscale_filter: Option<UpscaleFilter>,
+
+    /// Rotate (counter-clockwise) or mirror the picture, for screens the compositor doesn't know
+    /// are turned
+    #[arg(long, value_enum)]
+    pub transform: Option<Transform>,
+
+    /// Draw the shader at this aspect ratio, e.g. `16:9`, centered with `--clear-color` bars
+    /// around it, for shaders made for one shape of screen. `resolution` is the size inside the
+    /// bars
+    #[arg(long, value_name = "W:H", value_parser = parse_aspect)]
+    pub aspect: Option<Aspect>,
+
+    /// Format of the offscreen texture the shader draws into before it's copied to the surface.
+    /// rgba16-float avoids banding in feedback shaders that accumulate over many frames
+    #[arg(long, value_enum)]
+    pub pass_format: Option<PassFormat>,
+
+    /// Draw a single frame and leave it up instead of animating
+    #[arg(long, overrides_with = "no_once")]
+    pub once: bool,
+
+    /// Turn off `--once` when the config file turns it on
+    #[arg(long, overrides_with = "once")]
+    #[serde(skip)]
+    pub no_once: bool,
+
+    /// Switch to the default shader if one takes longer than this to draw, three frames in a
+    /// row. Waits for the gpu after every frame to measure it
+    #[arg(long, value_name = "MS")]
+    pub max_frame_ms: Option<u64>,
+
+    /// Only draw when the mouse, a uniform or the size changes, for shaders that don't animate
+    /// on their own. Still at most `--fps` times a second
+    #[arg(long, overrides_with = "no_only_on_change")]
+    pub only_on_change: bool,
+
+    /// Turn off `--only-on-change` when the config file turns it on
+    #[arg(long, overrides_with = "only_on_change")]
+    #[serde(skip)]
+    pub no_only_on_change: bool,
+
+    /// Color behind the shader as `R,G,B[,A]` in 0-1, an alpha below 1 makes the wallpaper
+    /// transparent
+    #[arg(long, value_name = "R,G,B[,A]", value_parser = parse_color)]
+    pub clear_color: Option<[f64; 4]>,
+
+    /// Fill the outputs with this color as `R,G,B[,A]` in 0-1 instead of running a shader. Drawn
+    /// once, like `--once`
+    #[arg(long, value_name = "R,G,B[,A]", value_parser = parse_color)]
+    pub color: Option<[f64; 4]>,
+
+    /// Layer-shell layer to put the wallpaper on
+    #[arg(long, value_enum)]
+    pub layer: Option<LayerKind>,
+
+    /// Edges to anchor the wallpaper to, e.g. `top,left`
+    #[arg(long, value_enum, value_delimiter = ',')]
+    pub anchor: Option<Vec<AnchorEdge>>,
+
+    /// Distance from the anchored edges as `top,right,bottom,left`
+    #[arg(long, value_name = "TOP,RIGHT,BOTTOM,LEFT", value_parser = parse_margin)]
+    pub margin: Option<[i32; 4]>,
+
+    /// Cover only this part of the output, given as `WxH+X+Y` from the top left. Overrides
+    /// `--anchor` and `--margin`
+    #[arg(long, value_name = "WxH+X+Y", value_parser = parse_geometry)]
+    pub geometry: Option<Geometry>,
+
+    /// Whether the wallpaper takes keyboard focus
+    #[arg(long, value_enum)]
+    pub keyboard_interactivity: Option<Interactivity>,
+
+    /// Space to reserve at the anchored edge, or -1 to ignore other surfaces' exclusive zones
+    #[arg(long, allow_hyphen_values = true)]
+    pub exclusive_zone: Option<i32>,
+
+    /// Render with the first gpu whose name contains this, see `--list-gpus`
+    #[arg(long)]
+    pub gpu: Option<String>,
+
+    /// Graphics API to render with
+    #[arg(long, value_enum)]
+    pub backend: Option<Backend>,
+
+    /// Render in software with wgpu's fallback adapter
+    #[arg(long, overrides_with = "no_force_fallback_adapter")]
+    pub force_fallback_adapter: bool,
+
+    /// Turn off `--force-fallback-adapter` when the config file turns it on
+    #[arg(long, overrides_with = "force_fallback_adapter")]
+    #[serde(skip)]
+    pub no_force_fallback_adapter: bool,
+
+    /// Print the available gpus and exit
+    #[arg(long)]
+    #[serde(skip)]
+    pub list_gpus: bool,
+
+    /// Print the names of the bundled example shaders and exit
+    #[arg(long)]
+    #[serde(skip)]
+    pub list_examples: bool,
+
+    /// Print what each output's adapter and surface support, then carry on as normal
+    #[arg(long)]
+    #[serde(skip)]
+    pub dump_caps: bool,
+
+    /// How to write log records to stderr
+    #[arg(long, value_enum)]
+    pub log_format: Option<LogFormat>,
+
+    /// Also write every frame to this file or FIFO as raw RGBA, for piping into an encoder. With
+    /// more than one output, give each its own in an `[[output]]` table
+    #[arg(long)]
+    pub pipe: Option<PathBuf>,
+
+    /// Keep the shader's `power` uniform up to date with the battery charge and whether the
+    /// machine is plugged in
+    #[arg(long, overrides_with = "no_power_uniform")]
+    pub power_uniform: bool,
+
+    /// Turn off `--power-uniform` when the config file turns it on
+    #[arg(long, overrides_with = "power_uniform")]
+    #[serde(skip)]
+    pub no_power_uniform: bool,
+
+    /// Listen for commands (see `control`) on a unix socket at this path
+    #[arg(long)]
+    pub control_socket: Option<PathBuf>,
+
+    /// Extra `f32` uniform for the shader, read as `custom.<name>` (repeatable)
+    #[arg(long = "uniform", value_name = "NAME=VALUE", value_parser = parse_uniform)]
+    #[serde(rename = "uniform", deserialize_with = "uniform_table")]
+    pub uniforms: Vec<(String, f32)>,
+
+    /// Keep custom uniforms fed from this source, see `provider` for what each declares
+    /// (repeatable)
+    #[arg(long = "provider", value_enum)]
+    #[serde(rename = "provider")]
+    pub providers: Option<Vec<Provider>>,
+
+    /// Define NAME for the shader's `#ifdef`s, replacing it with VALUE (default 1) in the code
+    /// (repeatable)
+    #[arg(long = "define", value_name = "NAME[=VALUE]", value_parser = parse_define)]
+    #[serde(rename = "define", deserialize_with = "define_table")]
+    pub defines: Vec<(String, String)>,
+
+    /// Render uncapped and without vsync for this many seconds, then print frame times and exit
+    #[arg(long, value_name = "SECS")]
+    #[serde(skip)]
+    pub benchmark: Option<f64>,
+
+    /// Config file to read instead of `$XDG_CONFIG_HOME/glpaper/config.toml`
+    #[arg(short, long)]
+    #[serde(skip)]
+    pub config: Option<PathBuf>,
+
+    #[arg(skip)]
+    #[serde(rename = "output")]
+    pub outputs: Vec<OutputOptions>,
+
+    #[command(subcommand)]
+    #[serde(skip)]
+    pub action: Option<Action>,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum Action {
+    /// Check that a shader compiles, without starting the wallpaper. Exits non-zero if it doesn't
+    Validate {
+        /// WGSL file defining `main_image`, or the name of a bundled example
+        shader: PathBuf,
+    },
+}
+
+/// A `WxH+X+Y` rectangle for `--geometry`.
+#[derive(Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(try_from = "String")]
+pub struct Geometry {
+    pub width: u32,
+    pub height: u32,
+    pub x: i32,
+    pub y: i32,
+}
+
+impl TryFrom<String> for Geometry {
+    type Error = String;
+
+    fn try_from(arg: String) -> Result<Self, String> {
+        parse_geometry(&arg)
+    }
+}
+
+/// `--seed`, no larger than [`Seed::MAX`].
+#[derive(Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(try_from = "u32")]
+pub struct Seed(pub u32);
+
+impl Seed {
+    /// The shader reads the seed as an `f32`, which holds every whole number up to 2^24 exactly
+    /// but not all of those above, so two seeds could give the same look.
+    pub const MAX: u32 = 1 << 24;
+}
+
+impl TryFrom<u32> for Seed {
+    type Error = String;
+
+    fn try_from(seed: u32) -> Result<Self, String> {
+        if seed > Seed::MAX {
+            return Err(format!("seed can be at most {}, got {}", Seed::MAX, seed));
+        }
+
+        Ok(Seed(seed))
+    }
+}
+
+/// Width over height, for `--aspect`.
+#[derive(Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(try_from = "String")]
+pub struct Aspect(pub f32);
+
+impl TryFrom<String> for Aspect {
+    type Error = String;
+
+    fn try_from(arg: String) -> Result<Self, String> {
+        parse_aspect(&arg)
+    }
+}
+
+/// An `--overlay` shader and how it's blended over what's below it.
+#[derive(Deserialize, Clone, Debug, PartialEq)]
+#[serde(try_from = "String")]
+pub struct Overlay {
+    pub shader: PathBuf,
+    pub blend: Blend,
+}
+
+impl TryFrom<String> for Overlay {
+    type Error = String;
+
+    fn try_from(arg: String) -> Result<Self, String> {
+        parse_overlay(&arg)
+    }
+}
+
+/// Picks outputs for `--output-only`, `--exclude-output` and `[[output]]` tables.
+#[derive(Deserialize, Clone, Debug, PartialEq, Eq)]
+#[serde(try_from = "String")]
+pub enum OutputSelector {
+    Name(String),
+    Make(String),
+    Model(String),
+    /// Matches descriptions containing it, as they tend to be long.
+    Description(String),
+}
+
+impl OutputSelector {
+    pub fn matches(&self, info: &OutputInfo) -> bool {
+        match self {
+            OutputSelector::Name(name) => info.name.as_ref() == Some(name),
+            OutputSelector::Make(make) => info.make == *make,
+            OutputSelector::Model(model) => info.model == *model,
+            OutputSelector::Description(description) => info
+                .description
+                .as_ref()
+                .is_some_and(|d| d.contains(description.as_str())),
+        }
+    }
+
+    /// Lower is more specific. Only one output has a given name, many can share a make.
+    fn precedence(&self) -> u8 {
+        match self {
+            OutputSelector::Name(_) => 0,
+            OutputSelector::Description(_) => 1,
+            OutputSelector::Model(_) => 2,
+            OutputSelector::Make(_) => 3,
+        }
+    }
+}
+
+impl TryFrom<String> for OutputSelector {
+    type Error = String;
+
+    fn try_from(arg: String) -> Result<Self, String> {
+        parse_output_selector(&arg)
+    }
+}
+
+/// One `[[output]]` table from the config file.
+#[derive(Deserialize, Debug, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct OutputOptions {
+    pub name: Option<String>,
+    pub make: Option<String>,
+    pub model: Option<String>,
+    pub description: Option<String>,
+    pub shader: Option<PathBuf>,
+    pub overlay: Option<Vec<Overlay>>,
+    pub fps: Option<u32>,
+    pub present_mode: Option<PresentMode>,
+    pub pipe: Option<PathBuf>,
+}
+
+impl OutputOptions {
+    /// What picks this table's output. A table is only meant to give one of `name`, `make`,
+    /// `model` or `description`, otherwise the most specific is used.
+    fn selector(&self) -> Option<OutputSelector> {
+        let field = |value: &Option<String>, selector: fn(String) -> OutputSelector| {
+            value.clone().map(selector)
+        };
+        field(&self.name, OutputSelector::Name)
+            .or_else(|| field(&self.description, OutputSelector::Description))
+            .or_else(|| field(&self.model, OutputSelector::Model))
+            .or_else(|| field(&self.make, OutputSelector::Make))
+    }
+}
+
+/// Everything an `OutputSurface` needs to know about how it should render.
+pub struct OutputSettings {
+    pub shader: Option<PathBuf>,
+    pub overlays: Vec<Overlay>,
+    /// Time between frames, or `None` to draw each frame when the last one has been shown.
+    pub frame_interval: Option<Duration>,
+    pub present_mode: wgpu::PresentMode,
+    pub uniforms: Vec<(String, f32)>,
+    pub providers: Vec<Provider>,
+    pub defines: Vec<(String, String)>,
+    pub clear_color: wgpu::Color,
+    /// Clear to `clear_color` without drawing the shader, for `--color`.
+    pub solid: bool,
+    pub time_scale: f32,
+    pub render_scale: f32,
+    pub report_full_resolution: bool,
+    pub msaa: u32,
+    pub feedback: bool,
+    pub compute: Option<PathBuf>,
+    pub upscale_filter: wgpu::FilterMode,
+    pub transform: Transform,
+    pub aspect: Option<f32>,
+    pub format: Option<wgpu::TextureFormat>,
+    pub pass_format: Option<wgpu::TextureFormat>,
+    pub once: bool,
+    pub only_on_change: bool,
+    pub max_frame_time: Option<Duration>,
+    /// Shared start for the shader clock with `--sync-outputs`, set by the caller.
+    pub epoch: Option<Instant>,
+    /// Where this output sits among all of them, set by the caller.
+    pub placement: Placement,
+    /// Shared by every output for the run, set by the caller.
+    pub seed: u32,
+    pub adapter: AdapterChoice,
+    pub dump_caps: bool,
+    pub pipe: Option<PathBuf>,
+}
+
+/// An output's place among all of them.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Placement {
+    /// Index of the output when sorted by name, so it stays the same between runs.
+    pub index: u32,
+    pub count: u32,
+    /// The output's bottom left corner within the box around every output, with y going up like
+    /// `frag_coord`.
+    pub offset: [f32; 2],
+    /// Size of the box around every output.
+    pub virtual_size: [f32; 2],
+}
+
+impl Placement {
+    /// The same placement measured in units `scale` times the size of a logical pixel.
+    pub fn scaled(self, scale: f32) -> Self {
+        Placement {
+            offset: self.offset.map(|c| c * scale),
+            virtual_size: self.virtual_size.map(|c| c * scale),
+            ..self
+        }
+    }
+}
+
+#[derive(ValueEnum, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum PresentMode {
+    AutoVsync,
+    AutoNoVsync,
+    Fifo,
+    FifoRelaxed,
+    Immediate,
+    Mailbox,
+}
+
+impl From<PresentMode> for wgpu::PresentMode {
+    fn from(mode: PresentMode) -> Self {
+        match mode {
+            PresentMode::AutoVsync => wgpu::PresentMode::AutoVsync,
+            PresentMode::AutoNoVsync => wgpu::PresentMode::AutoNoVsync,
+            PresentMode::Fifo => wgpu::PresentMode::Fifo,
+            PresentMode::FifoRelaxed => wgpu::PresentMode::FifoRelaxed,
+            PresentMode::Immediate => wgpu::PresentMode::Immediate,
+            PresentMode::Mailbox => wgpu::PresentMode::Mailbox,
+        }
+    }
+}
+
+/// How an overlay is combined with what's drawn below it.
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Blend {
+    /// Covers what's below by its alpha
+    Alpha,
+    /// Adds its color, weighted by its alpha
+    Add,
+    /// Brightens what's below, ignoring alpha
+    Screen,
+}
+
+impl From<Blend> for wgpu::BlendState {
+    fn from(blend: Blend) -> Self {
+        let color = match blend {
+            Blend::Alpha => return wgpu::BlendState::ALPHA_BLENDING,
+            Blend::Add => wgpu::BlendComponent {
+                src_factor: wgpu::BlendFactor::SrcAlpha,
+                dst_factor: wgpu::BlendFactor::One,
+                operation: wgpu::BlendOperation::Add,
+            },
+            Blend::Screen => wgpu::BlendComponent {
+                src_factor: wgpu::BlendFactor::One,
+                dst_factor: wgpu::BlendFactor::OneMinusSrc,
+                operation: wgpu::BlendOperation::Add,
+            },
+        };
+
+        wgpu::BlendState {
+            color,
+            alpha: wgpu::BlendComponent::OVER,
+        }
+    }
+}
+
+/// Built in [`UniformProvider`](crate::renderer::provider::UniformProvider)s.
+#[derive(ValueEnum, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum Provider {
+    CpuLoad,
+}
+
+impl Provider {
+    /// Name of the uniform the provider declares, or their prefix when it declares several.
+    pub fn uniform_name(self) -> &'static str {
+        match self {
+            Provider::CpuLoad => "cpu_load",
+        }
+    }
+
+    /// Whether `name` is one of the uniforms the provider declares.
+    fn declares(self, name: &str) -> bool {
+        let prefix = self.uniform_name();
+        name == prefix
+            || name
+                .strip_prefix(prefix)
+                .and_then(|rest| rest.strip_prefix('_'))
+                .is_some_and(|index| index.parse::<usize>().is_ok())
+    }
+}
+
+#[derive(ValueEnum, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum UpscaleFilter {
+    Nearest,
+    Linear,
+}
+
+impl From<UpscaleFilter> for wgpu::FilterMode {
+    fn from(filter: UpscaleFilter) -> Self {
+        match filter {
+            UpscaleFilter::Nearest => wgpu::FilterMode::Nearest,
+            UpscaleFilter::Linear => wgpu::FilterMode::Linear,
+        }
+    }
+}
+
+/// Same order as `wl_output::Transform`, which is also what the upscale shader switches on.
+#[derive(ValueEnum, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum Transform {
+    Normal,
+    #[value(name = "90")]
+    #[serde(rename = "90")]
+    Rotate90,
+    #[value(name = "180")]
+    #[serde(rename = "180")]
+    Rotate180,
+    #[value(name = "270")]
+    #[serde(rename = "270")]
+    Rotate270,
+    Flipped,
+    #[value(name = "flipped-90")]
+    #[serde(rename = "flipped-90")]
+    Flipped90,
+    #[value(name = "flipped-180")]
+    #[serde(rename = "flipped-180")]
+    Flipped180,
+    #[value(name = "flipped-270")]
+    #[serde(rename = "flipped-270")]
+    Flipped270,
+}
+
+impl Transform {
+    /// Whether the picture is on its side, so its width and height are swapped on screen.
+    pub fn is_rotated(self) -> bool {
+        matches!(
+            self,
+            Transform::Rotate90
+                | Transform::Rotate270
+                | Transform::Flipped90
+                | Transform::Flipped270
+        )
+    }
+
+    /// `(width, height)` of the picture shown on a surface of `size`.
+    pub fn picture_size(self, (width, height): (u32, u32)) -> (u32, u32) {
+        if self.is_rotated() {
+            (height, width)
+        } else {
+            (width, height)
+        }
+    }
+
+    /// Maps a point on the surface to the point of the picture shown there, both as fractions of
+    /// their size with the origin at the bottom left. Matches `untransform` in `upscale.wgsl`.
+    pub fn surface_to_picture(self, [x, y]: [f32; 2]) -> [f32; 2] {
+        let [x, y] = match self as u32 % 4 {
+            1 => [y, 1.0 - x],
+            2 => [1.0 - x, 1.0 - y],
+            3 => [1.0 - y, x],
+            _ => [x, y],
+        };
+        if self as u32 >= 4 {
+            [1.0 - x, y]
+        } else {
+            [x, y]
+        }
+    }
+}
+
+#[derive(ValueEnum, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum SurfaceFormat {
+    Bgra8Unorm,
+    Bgra8UnormSrgb,
+    Rgba8Unorm,
+    Rgba8UnormSrgb,
+    Rgb10a2Unorm,
+    Rgba16Float,
+}
+
+impl From<SurfaceFormat> for wgpu::TextureFormat {
+    fn from(format: SurfaceFormat) -> Self {
+        match format {
+            SurfaceFormat::Bgra8Unorm => wgpu::TextureFormat::Bgra8Unorm,
+            SurfaceFormat::Bgra8UnormSrgb => wgpu::TextureFormat::Bgra8UnormSrgb,
+            SurfaceFormat::Rgba8Unorm => wgpu::TextureFormat::Rgba8Unorm,
+            SurfaceFormat::Rgba8UnormSrgb => wgpu::TextureFormat::Rgba8UnormSrgb,
+            SurfaceFormat::Rgb10a2Unorm => wgpu::TextureFormat::Rgb10a2Unorm,
+            SurfaceFormat::Rgba16Float => wgpu::TextureFormat::Rgba16Float,
+        }
+    }
+}
+
+#[derive(ValueEnum, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum PassFormat {
+    Rgba8Unorm,
+    Rgba16Float,
+}
+
+impl From<PassFormat> for wgpu::TextureFormat {
+    fn from(format: PassFormat) -> Self {
+        match format {
+            PassFormat::Rgba8Unorm => wgpu::TextureFormat::Rgba8Unorm,
+            PassFormat::Rgba16Float => wgpu::TextureFormat::Rgba16Float,
+        }
+    }
+}
+
+#[derive(ValueEnum, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum LayerKind {
+    Background,
+    Bottom,
+    Top,
+    Overlay,
+}
+
+impl From<LayerKind> for Layer {
+    fn from(layer: LayerKind) -> Self {
+        match layer {
+            LayerKind::Background => Layer::Background,
+            LayerKind::Bottom => Layer::Bottom,
+            LayerKind::Top => Layer::Top,
+            LayerKind::Overlay => Layer::Overlay,
+        }
+    }
+}
+
+#[derive(ValueEnum, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum AnchorEdge {
+    Top,
+    Bottom,
+    Left,
+    Right,
+}
+
+impl From<AnchorEdge> for Anchor {
+    fn from(edge: AnchorEdge) -> Self {
+        match edge {
+            AnchorEdge::Top => Anchor::TOP,
+            AnchorEdge::Bottom => Anchor::BOTTOM,
+            AnchorEdge::Left => Anchor::LEFT,
+            AnchorEdge::Right => Anchor::RIGHT,
+        }
+    }
+}
+
+#[derive(ValueEnum, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum Interactivity {
+    None,
+    Exclusive,
+    OnDemand,
+}
+
+impl From<Interactivity> for KeyboardInteractivity {
+    fn from(interactivity: Interactivity) -> Self {
+        match interactivity {
+            Interactivity::None => KeyboardInteractivity::None,
+            Interactivity::Exclusive => KeyboardInteractivity::Exclusive,
+            Interactivity::OnDemand => KeyboardInteractivity::OnDemand,
+        }
+    }
+}
+
+#[derive(ValueEnum, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum Backend {
+    Vulkan,
+    Gl,
+    All,
+}
+
+impl From<Backend> for wgpu::Backends {
+    fn from(backend: Backend) -> Self {
+        match backend {
+            Backend::Vulkan => wgpu::Backends::VULKAN,
+            Backend::Gl => wgpu::Backends::GL,
+            Backend::All => wgpu::Backends::all(),
+        }
+    }
+}
+
+#[derive(ValueEnum, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum LogFormat {
+    Text,
+    /// One JSON object per line, for supervisors and log collectors
+    Json,
+}
+
+impl Options {
+    /// Parses the command line and fills in anything it left unset from the config file.
+    pub fn load() -> Result<Self> {
+        let cli = Options::parse();
+
+        let file = match &cli.config {
+            Some(path) => Self::from_file(path)?,
+            None => match default_config_path() {
+                Some(path) if path.exists() => Self::from_file(&path)?,
+                _ => Options::default(),
+            },
+        };
+
+        let options = cli.merge(file);
+        options.check_uniforms()?;
+        Ok(options)
+    }
+
+    /// Rejects custom uniforms declared twice, by two `--provider`s or a `--provider` and a
+    /// `--uniform`, which the shader would fail to compile with.
+    fn check_uniforms(&self) -> Result<()> {
+        let providers = self.providers.as_deref().unwrap_or_default();
+        for (i, provider) in providers.iter().enumerate() {
+            if providers[..i].contains(provider) {
+                bail!(
+                    "--provider declaring {} given twice",
+                    provider.uniform_name()
+                );
+            }
+            if let Some((name, _)) = self
+                .uniforms
+                .iter()
+                .find(|(name, _)| provider.declares(name))
+            {
+                bail!("--uniform {} is already declared by a --provider", name);
+            }
+        }
+
+        Ok(())
+    }
+
+    fn from_file(path: &Path) -> Result<Self> {
+        let contents = fs::read_to_string(path)
+            .with_context(|| format!("couldnt read config {}", path.display()))?;
+        toml::from_str(&contents).with_context(|| format!("invalid config {}", path.display()))
+    }
+
+    /// Fills unset values from `file`. Flags given here also override the `[[output]]` tables so
+    /// that the command line always has the last word. A switch the file turns on can be turned
+    /// back off with its `--no-` flag.
+    fn merge(self, file: Options) -> Self {
+        let outputs = file
+            .outputs
+            .into_iter()
+            .map(|output| OutputOptions {
+                shader: self.shader.clone().or(output.shader),
+                overlay: self.overlays.clone().or(output.overlay),
+                fps: self.fps.or(output.fps),
+                present_mode: self.present_mode.or(output.present_mode),
+                pipe: self.pipe.clone().or(output.pipe),
+                ..output
+            })
+            .collect();
+
+        let mut uniforms = file.uniforms;
+        for (name, value) in self.uniforms {
+            match uniforms.iter_mut().find(|(n, _)| *n == name) {
+                Some(uniform) => uniform.1 = value,
+                None => uniforms.push((name, value)),
+            }
+        }
+
+        let mut defines = file.defines;
+        for (name, value) in self.defines {
+            match defines.iter_mut().find(|(n, _)| *n == name) {
+                Some(define) => define.1 = value,
+                None => defines.push((name, value)),
+            }
+        }
+
+        Options {
+            shader: self.shader.or(file.shader),
+            overlays: self.overlays.or(file.overlays),
+            fps: self.fps.or(file.fps),
+            present_mode: self.present_mode.or(file.present_mode),
+            time_scale: self.time_scale.or(file.time_scale),
+            sync_outputs: !self.no_sync_outputs && (self.sync_outputs || file.sync_outputs),
+            no_sync_outputs: self.no_sync_outputs,
+            mirror: !self.no_mirror && (self.mirror || file.mirror),
+            no_mirror: self.no_mirror,
+            seed: self.seed.or(file.seed),
+            output_only: self.output_only.or(file.output_only),
+            exclude_output: self.exclude_output.or(file.exclude_output),
+            render_scale: self.render_scale.or(file.render_scale),
+            report_full_resolution: !self.no_report_full_resolution
+                && (self.report_full_resolution || file.report_full_resolution),
+            no_report_full_resolution: self.no_report_full_resolution,
+            msaa: self.msaa.or(file.msaa),
+            feedback: !self.no_feedback && (self.feedback || file.feedback),
+            no_feedback: self.no_feedback,
+            compute: self.compute.or(file.compute),
+            upscale_filter: self.upscale_filter.or(file.upscale_filter),
+            transform: self.transform.or(file.transform),
+            aspect: self.aspect.or(file.aspect),
+            format: self.format.or(file.format),
+            pass_format: self.pass_format.or(file.pass_format),
+            once: !self.no_once && (self.once || file.once),
+            no_once: self.no_once,
+            only_on_change: !self.no_only_on_change && (self.only_on_change || file.only_on_change),
+            no_only_on_change: self.no_only_on_change,
+            max_frame_ms: self.max_frame_ms.or(file.max_frame_ms),
+            clear_color: self.clear_color.or(file.clear_color),
+            color: self.color.or(file.color),
+            layer: self.layer.or(file.layer),
+            anchor: self.anchor.or(file.anchor),
+            margin: self.margin.or(file.margin),
+            geometry: self.geometry.or(file.geometry),
+            keyboard_interactivity: self.keyboard_interactivity.or(file.keyboard_interactivity),
+            exclusive_zone: self.exclusive_zone.or(file.exclusive_zone),
+            gpu: self.gpu.or(file.gpu),
+            backend: self.backend.or(file.backend),
+            force_fallback_adapter: !self.no_force_fallback_adapter
+                && (self.force_fallback_adapter || file.force_fallback_adapter),
+            no_force_fallback_adapter: self.no_force_fallback_adapter,
+            list_gpus: self.list_gpus,
+            list_examples: self.list_examples,
+            dump_caps: self.dump_caps,
+            log_format: self.log_format.or(file.log_format),
+            pipe: self.pipe.or(file.pipe),
+            power_uniform: !self.no_power_uniform && (self.power_uniform || file.power_uniform),
+            no_power_uniform: self.no_power_uniform,
+            control_socket: self.control_socket.or(file.control_socket),
+            uniforms,
+            providers: self.providers.or(file.providers),
+            defines,
+            benchmark: self.benchmark,
+            config: self.config,
+            outputs,
+            action: self.action,
+        }
+    }
+
+    pub fn layer(&self) -> Layer {
+        self.layer.unwrap_or(LayerKind::Background).into()
+    }
+
+    pub fn anchor(&self) -> Anchor {
+        if self.geometry.is_some() {
+            return Anchor::TOP | Anchor::LEFT;
+        }
+
+        match &self.anchor {
+            Some(edges) => edges
+                .iter()
+                .fold(Anchor::empty(), |anchor, &edge| anchor | edge.into()),
+            None => Anchor::all(),
+        }
+    }
+
+    pub fn margin(&self) -> [i32; 4] {
+        match self.geometry {
+            Some(geometry) => [geometry.y, 0, 0, geometry.x],
+            None => self.margin.unwrap_or_default(),
+        }
+    }
+
+    /// Size to request for the layer on an output of `output_size`. A dimension stretched between
+    /// two anchored edges is sized by the compositor (0), anything else has to be given
+    /// explicitly.
+    pub fn layer_size(&self, (width, height): (i32, i32)) -> (u32, u32) {
+        if let Some(geometry) = self.geometry {
+            return (geometry.width, geometry.height);
+        }
+
+        let anchor = self.anchor();
+        (
+            if anchor.contains(Anchor::LEFT | Anchor::RIGHT) {
+                0
+            } else {
+                width.unsigned_abs()
+            },
+            if anchor.contains(Anchor::TOP | Anchor::BOTTOM) {
+                0
+            } else {
+                height.unsigned_abs()
+            },
+        )
+    }
+
+    /// Edges to anchor a layer of `layer_size` to. The protocol only lets the compositor pick a
+    /// dimension stretched between two edges, so one that came out 0 because the output's size
+    /// isn't known yet is stretched over the whole output, as it would have been sized anyway.
+    pub fn layer_anchor(&self, (width, height): (u32, u32)) -> Anchor {
+        let mut anchor = self.anchor();
+        if width == 0 {
+            anchor |= Anchor::LEFT | Anchor::RIGHT;
+        }
+        if height == 0 {
+            anchor |= Anchor::TOP | Anchor::BOTTOM;
+        }
+        anchor
+    }
+
+    pub fn backends(&self) -> wgpu::Backends {
+        self.backend.unwrap_or(Backend::All).into()
+    }
+
+    pub fn keyboard_interactivity(&self) -> KeyboardInteractivity {
+        self.keyboard_interactivity
+            .unwrap_or(Interactivity::None)
+            .into()
+    }
+
+    /// Whether to draw on `info` at all, going by `--output-only` and `--exclude-output`.
+    pub fn wants_output(&self, info: &OutputInfo) -> bool {
+        let listed = |selectors: &Vec<OutputSelector>| selectors.iter().any(|s| s.matches(info));
+        self.output_only.as_ref().is_none_or(listed)
+            && !self.exclude_output.as_ref().is_some_and(listed)
+    }
+
+    /// The `[[output]]` table for the output whose selectors `matches` accepts. Of several, the
+    /// one with the most specific selector is used, and of those the first.
+    fn output_table(&self, matches: impl Fn(&OutputSelector) -> bool) -> Option<&OutputOptions> {
+        self.outputs
+            .iter()
+            .filter_map(|output| Some((output.selector().filter(|s| matches(s))?, output)))
+            .min_by_key(|(selector, _)| selector.precedence())
+            .map(|(_, output)| output)
+    }
+
+    /// Settings for `info`, with the `[[output]]` table that picks it most specifically applied if
+    /// there is one.
+    pub fn for_output(&self, info: Option<&OutputInfo>) -> OutputSettings {
+        let output = info.and_then(|info| self.output_table(|selector| selector.matches(info)));
+
+        let fps = output
+            .and_then(|o| o.fps)
+            .or(self.fps)
+            .unwrap_or(DEFAULT_FPS);
+        let present_mode = output
+            .and_then(|o| o.present_mode)
+            .or(self.present_mode)
+            // Wayland is inherently a mailbox system.
+            .unwrap_or(PresentMode::Mailbox);
+
+        let (frame_interval, present_mode) = match self.benchmark {
+            Some(_) => (Some(Duration::ZERO), PresentMode::AutoNoVsync),
+            None => (frame_interval(fps), present_mode),
+        };
+
+        OutputSettings {
+            shader: output
+                .and_then(|o| o.shader.clone())
+                .or_else(|| self.shader.clone()),
+            overlays: output
+                .and_then(|o| o.overlay.clone())
+                .or_else(|| self.overlays.clone())
+                .unwrap_or_default(),
+            frame_interval,
+            present_mode: present_mode.into(),
+            uniforms: self.uniforms.clone(),
+            providers: self.providers.clone().unwrap_or_default(),
+            defines: self.defines.clone(),
+            clear_color: self
+                .color
+                .or(self.clear_color)
+                .map_or(wgpu::Color::BLACK, to_color),
+            solid: self.color.is_some(),
+            time_scale: self.time_scale.unwrap_or(1.0),
+            render_scale: self.render_scale.unwrap_or(1.0),
+            report_full_resolution: self.report_full_resolution,
+            msaa: self.msaa.unwrap_or(1),
+            feedback: self.feedback,
+            compute: self.compute.clone(),
+            upscale_filter: self.upscale_filter.unwrap_or(UpscaleFilter::Linear).into(),
+            transform: self.transform.unwrap_or(Transform::Normal),
+            aspect: self.aspect.map(|aspect| aspect.0),
+            format: self.format.map(Into::into),
+            pass_format: self.pass_format.map(Into::into),
+            once: self.once || self.color.is_some(),
+            only_on_change: self.only_on_change,
+            max_frame_time: self.max_frame_ms.map(Duration::from_millis),
+            epoch: None,
+            placement: Placement::default(),
+            seed: self.seed.map_or(0, |seed| seed.0),
+            dump_caps: self.dump_caps,
+            adapter: AdapterChoice {
+                backends: self.backends(),
+                gpu: self.gpu.clone(),
+                force_fallback: self.force_fallback_adapter,
+            },
+            pipe: output
+                .and_then(|o| o.pipe.clone())
+                .or_else(|| self.pipe.clone()),
+        }
+    }
+}
+
+/// `None` for 0, which leaves the pace to the compositor's frame callbacks.
+pub fn frame_interval(fps: u32) -> Option<Duration> {
+    (fps > 0).then(|| Duration::from_secs_f64(1.0 / fps as f64))
+}
+
+fn parse_uniform(arg: &str) -> Result<(String, f32), String> {
+    let (name, value) = arg
+        .split_once('=')
+        .ok_or_else(|| format!("expected NAME=VALUE, got {:?}", arg))?;
+
+    if !is_identifier(name) {
+        return Err(format!("{:?} is not a valid uniform name", name));
+    }
+
+    let value = value
+        .parse()
+        .map_err(|_| format!("{:?} is not a number", value))?;
+
+    Ok((name.to_string(), value))
+}
+
+fn parse_define(arg: &str) -> Result<(String, String), String> {
+    let (name, value) = arg.split_once('=').unwrap_or((arg, "1"));
+    if !is_identifier(name) {
+        return Err(format!("{:?} is not a valid define name", name));
+    }
+
+    Ok((name.to_string(), value.to_string()))
+}
+
+/// Whether `name` can be used as a WGSL identifier.
+pub fn is_identifier(name: &str) -> bool {
+    let mut chars = name.chars();
+    chars
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+fn parse_render_scale(arg: &str) -> Result<f32, String> {
+    match arg.parse::<f32>() {
+        Ok(scale) if scale.is_finite() && scale > 0.0 => Ok(scale),
+        _ => Err(format!("expected a positive number, got {:?}", arg)),
+    }
+}
+
+fn parse_msaa(arg: &str) -> Result<u32, String> {
+    match arg.parse::<u32>() {
+        Ok(samples @ (1 | 2 | 4 | 8)) => Ok(samples),
+        _ => Err(format!("expected 1, 2, 4 or 8, got {:?}", arg)),
+    }
+}
+
+fn to_color([r, g, b, a]: [f64; 4]) -> wgpu::Color {
+    wgpu::Color { r, g, b, a }
+}
+
+fn parse_color(arg: &str) -> Result<[f64; 4], String> {
+    let channels = arg
+        .split(',')
+        .map(|channel| channel.trim().parse::<f64>())
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| format!("invalid color {:?}: {}", arg, e))?;
+
+    if channels
+        .iter()
+        .any(|channel| !(0.0..=1.0).contains(channel))
+    {
+        return Err(format!(
+            "color channels must be between 0 and 1, got {:?}",
+            arg
+        ));
+    }
+
+    match channels[..] {
+        [r, g, b] => Ok([r, g, b, 1.0]),
+        [r, g, b, a] => Ok([r, g, b, a]),
+        _ => Err(format!("expected 3 or 4 channels, got {:?}", arg)),
+    }
+}
+
+fn parse_margin(arg: &str) -> Result<[i32; 4], String> {
+    let margins = arg
+        .split(',')
+        .map(|margin| margin.trim().parse::<i32>())
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| format!("invalid margin {:?}: {}", arg, e))?;
+
+    margins
+        .try_into()
+        .map_err(|_| format!("expected 4 margins, got {:?}", arg))
+}
+
+fn parse_output_selector(arg: &str) -> Result<OutputSelector, String> {
+    let selector = match arg.split_once('=') {
+        None => OutputSelector::Name(arg.to_string()),
+        Some(("name", name)) => OutputSelector::Name(name.to_string()),
+        Some(("make", make)) => OutputSelector::Make(make.to_string()),
+        Some(("model", model)) => OutputSelector::Model(model.to_string()),
+        Some(("description", description)) => OutputSelector::Description(description.to_string()),
+        Some((field, _)) => {
+            return Err(format!(
+                "expected name, make, model or description before =, got {:?}",
+                field
+            ))
+        }
+    };
+
+    Ok(selector)
+}
+
+fn parse_overlay(arg: &str) -> Result<Overlay, String> {
+    let blend = arg
+        .split_once(':')
+        .and_then(|(blend, shader)| Some((Blend::from_str(blend, true).ok()?, shader)));
+
+    Ok(match blend {
+        Some((blend, shader)) => Overlay {
+            shader: shader.into(),
+            blend,
+        },
+        None => Overlay {
+            shader: arg.into(),
+            blend: Blend::Alpha,
+        },
+    })
+}
+
+fn parse_geometry(arg: &str) -> Result<Geometry, String> {
+    let invalid = || format!("expected WxH+X+Y, got {:?}", arg);
+
+    let (size, position) = arg.split_once('+').ok_or_else(invalid)?;
+    let (width, height) = size.split_once('x').ok_or_else(invalid)?;
+    let (x, y) = position.split_once('+').ok_or_else(invalid)?;
+
+    let geometry = Geometry {
+        width: width.parse().map_err(|_| invalid())?,
+        height: height.parse().map_err(|_| invalid())?,
+        x: x.parse().map_err(|_| invalid())?,
+        y: y.parse().map_err(|_| invalid())?,
+    };
+    if geometry.width == 0 || geometry.height == 0 {
+        return Err(format!("geometry needs a non-zero size, got {:?}", arg));
+    }
+
+    Ok(geometry)
+}
+
+fn parse_seed(arg: &str) -> Result<Seed, String> {
+    arg.parse::<u32>()
+        .map_err(|_| format!("expected a whole number, got {:?}", arg))?
+        .try_into()
+}
+
+fn parse_aspect(arg: &str) -> Result<Aspect, String> {
+    let invalid = || format!("expected W:H, got {:?}", arg);
+
+    let (width, height) = arg.split_once(':').ok_or_else(invalid)?;
+    let width = width.trim().parse::<f32>().map_err(|_| invalid())?;
+    let height = height.trim().parse::<f32>().map_err(|_| invalid())?;
+    if !(width > 0.0 && height > 0.0) {
+        return Err(format!("aspect ratio needs a non-zero size, got {:?}", arg));
+    }
+
+    Ok(Aspect(width / height))
+}
+
+/// Reads a `[uniform]` table of `name = value` pairs.
+fn uniform_table<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Vec<(String, f32)>, D::Error> {
+    let table = BTreeMap::<String, f32>::deserialize(deserializer)?;
+    Ok(table.into_iter().collect())
+}
+
+/// A `[define]` table, where values can be written as strings or as plain TOML numbers and bools.
+fn define_table<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Vec<(String, String)>, D::Error> {
+    let table = BTreeMap::<String, toml::Value>::deserialize(deserializer)?;
+    Ok(table
+        .into_iter()
+        .map(|(name, value)| match value {
+            toml::Value::String(value) => (name, value),
+            value => (name, value.to_string()),
+        })
+        .collect())
+}
+
+fn default_config_path() -> Option<PathBuf> {
+    let config_home = env::var_os("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))?;
+
+    Some(config_home.join("glpaper").join("config.toml"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The command line `args` merged over a config file of `toml`.
+    fn load(args: &[&str], toml: &str) -> Options {
+        let cli = Options::try_parse_from(std::iter::once("glpaper").chain(args.iter().copied()))
+            .unwrap();
+        let file: Options = toml::from_str(toml).unwrap();
+        cli.merge(file)
+    }
+
+    #[test]
+    fn command_line_over_file() {
+        let cases: &[(&[&str], &str, bool)] = &[
+            (&[], "", false),
+            (&[], "feedback = true", true),
+            (&["--feedback"], "", true),
+            (&["--feedback"], "feedback = false", true),
+            (&["--no-feedback"], "feedback = true", false),
+            // the last of the two on the command line wins
+            (&["--feedback", "--no-feedback"], "", false),
+            (&["--no-feedback", "--feedback"], "feedback = true", true),
+        ];
+        for (args, toml, feedback) in cases {
+            assert_eq!(
+                load(args, toml).feedback,
+                *feedback,
+                "{:?} over {:?}",
+                args,
+                toml
+            );
+        }
+
+        let options = load(&["--fps", "60"], "fps = 30\ntime_scale = 0.5");
+        assert_eq!(options.fps, Some(60));
+        assert_eq!(options.time_scale, Some(0.5));
+
+        // single uniforms and defines are replaced, the rest of the file's are kept
+        let options = load(
+            &["--uniform", "a=3", "--uniform", "c=4", "--define", "X=2"],
+            "[uniform]\na = 1.0\nb = 2.0\n[define]\nX = 1\nY = true",
+        );
+        assert_eq!(
+            options.uniforms,
+            [("a".into(), 3.0), ("b".into(), 2.0), ("c".into(), 4.0)]
+        );
+        assert_eq!(
+            options.defines,
+            [("X".into(), "2".into()), ("Y".into(), "true".into())]
+        );
+    }
+
+    #[test]
+    fn most_specific_output_table() {
+        // DP-1, a Dell U2720Q, and HDMI-A-1, an LG 27GL850
+        let dp1 = |selector: &OutputSelector| match selector {
+            OutputSelector::Name(name) => name == "DP-1",
+            OutputSelector::Make(make) => make == "Dell",
+            OutputSelector::Model(model) => model == "U2720Q",
+            OutputSelector::Description(description) => {
+                "Dell U2720Q (DP-1)".contains(description.as_str())
+            }
+        };
+        let hdmi = |selector: &OutputSelector| match selector {
+            OutputSelector::Name(name) => name == "HDMI-A-1",
+            OutputSelector::Make(make) => make == "LG",
+            OutputSelector::Model(model) => model == "27GL850",
+            OutputSelector::Description(description) => {
+                "LG 27GL850 (HDMI-A-1)".contains(description.as_str())
+            }
+        };
+        let picked = |toml: &str, matches: &dyn Fn(&OutputSelector) -> bool| {
+            let options: Options = toml::from_str(toml).unwrap();
+            options
+                .output_table(matches)
+                .and_then(|output| output.shader.clone())
+        };
+
+        let tables = r#"
+            [[output]]
+            make = "Dell"
+            shader = "make.wgsl"
+
+            [[output]]
+            model = "U2720Q"
+            shader = "model.wgsl"
+
+            [[output]]
+            description = "U2720Q"
+            shader = "description.wgsl"
+
+            [[output]]
+            name = "DP-1"
+            shader = "name.wgsl"
+        "#;
+        assert_eq!(picked(tables, &dp1), Some("name.wgsl".into()));
+        assert_eq!(picked(tables, &hdmi), None);
+
+        // without the name table the description is next, whatever the order
+        let tables = r#"
+            [[output]]
+            make = "Dell"
+            shader = "make.wgsl"
+
+            [[output]]
+            description = "U2720Q"
+            shader = "description.wgsl"
+
+            [[output]]
+            model = "U2720Q"
+            shader = "model.wgsl"
+        "#;
+        assert_eq!(picked(tables, &dp1), Some("description.wgsl".into()));
+
+        // a table giving several fields only goes by the most specific of them
+        let tables = r#"
+            [[output]]
+            make = "Dell"
+            shader = "make.wgsl"
+
+            [[output]]
+            make = "LG"
+            name = "DP-1"
+            shader = "name.wgsl"
+        "#;
+        assert_eq!(picked(tables, &dp1), Some("name.wgsl".into()));
+        assert_eq!(picked(tables, &hdmi), None);
+
+        // equally specific tables go by order
+        let tables = r#"
+            [[output]]
+            make = "Dell"
+            shader = "first.wgsl"
+
+            [[output]]
+            make = "Dell"
+            shader = "second.wgsl"
+        "#;
+        assert_eq!(picked(tables, &dp1), Some("first.wgsl".into()));
+    }
+
+    #[test]
+    fn command_line_over_output_tables() {
+        let tables = r#"
+            [[output]]
+            name = "DP-1"
+            shader = "table.wgsl"
+            fps = 30
+            pipe = "/tmp/table.fifo"
+        "#;
+
+        let options = load(&[], tables);
+        assert_eq!(options.outputs[0].shader, Some("table.wgsl".into()));
+        assert_eq!(options.outputs[0].fps, Some(30));
+        assert_eq!(options.outputs[0].pipe, Some("/tmp/table.fifo".into()));
+
+        let options = load(
+            &[
+                "--shader",
+                "cli.wgsl",
+                "--fps",
+                "60",
+                "--pipe",
+                "/tmp/cli.fifo",
+            ],
+            tables,
+        );
+        assert_eq!(options.outputs[0].shader, Some("cli.wgsl".into()));
+        assert_eq!(options.outputs[0].fps, Some(60));
+        assert_eq!(options.outputs[0].pipe, Some("/tmp/cli.fifo".into()));
+    }
+
+    #[test]
+    fn parse_colors() {
+        let cases = [
+            ("1,0.5,0", Some([1.0, 0.5, 0.0, 1.0])),
+            (" 0 , 0 , 0 , 0.25 ", Some([0.0, 0.0, 0.0, 0.25])),
+            ("1,0", None),
+            ("1,0,0,1,1", None),
+            ("1,0,2", None),
+            ("-0.5,0,0", None),
+            ("red", None),
+            ("", None),
+        ];
+        for (arg, expected) in cases {
+            assert_eq!(parse_color(arg).ok(), expected, "{:?}", arg);
+        }
+    }
+
+    #[test]
+    fn parse_margins() {
+        let cases = [
+            ("0,0,0,0", Some([0, 0, 0, 0])),
+            ("10, -5, 20, 0", Some([10, -5, 20, 0])),
+            ("1,2,3", None),
+            ("1,2,3,4,5", None),
+            ("1,2,x,4", None),
+        ];
+        for (arg, expected) in cases {
+            assert_eq!(parse_margin(arg).ok(), expected, "{:?}", arg);
+        }
+    }
+
+    #[test]
+    fn parse_geometries() {
+        let geometry = |width, height, x, y| Geometry {
+            width,
+            height,
+            x,
+            y,
+        };
+        let cases = [
+            ("1920x1080+0+0", Some(geometry(1920, 1080, 0, 0))),
+            ("800x600+-100+50", Some(geometry(800, 600, -100, 50))),
+            ("0x600+0+0", None),
+            ("800x600", None),
+            ("800x600+0", None),
+            ("800+0+0", None),
+            ("-800x600+0+0", None),
+            ("800x600+a+0", None),
+        ];
+        for (arg, expected) in cases {
+            assert_eq!(parse_geometry(arg).ok(), expected, "{:?}", arg);
+        }
+    }
+
+    #[test]
+    fn parse_uniforms_and_defines() {
+        let cases = [
+            ("exposure=1.5", Some(("exposure", 1.5))),
+            ("_x2=-3", Some(("_x2", -3.0))),
+            ("exposure", None),
+            ("exposure=", None),
+            ("exposure=bright", None),
+            ("2x=1", None),
+            ("my-value=1", None),
+        ];
+        for (arg, expected) in cases {
+            let expected = expected.map(|(name, value)| (name.to_string(), value));
+            assert_eq!(parse_uniform(arg).ok(), expected, "{:?}", arg);
+        }
+
+        let cases = [
+            ("FAST", Some(("FAST", "1"))),
+            ("STEPS=64", Some(("STEPS", "64"))),
+            (
+                "TINT=vec3(1.0, 0.5, 0.0)",
+                Some(("TINT", "vec3(1.0, 0.5, 0.0)")),
+            ),
+            ("EMPTY=", Some(("EMPTY", ""))),
+            ("2D", None),
+            ("A B=1", None),
+        ];
+        for (arg, expected) in cases {
+            let expected = expected.map(|(name, value)| (name.to_string(), value.to_string()));
+            assert_eq!(parse_define(arg).ok(), expected, "{:?}", arg);
+        }
+    }
+
+    #[test]
+    fn layer_size_and_anchor() {
+        // arguments, output size, layer size, anchor
+        type Case<'a> = (&'a [&'a str], (i32, i32), (u32, u32), Anchor);
+        let all = Anchor::all();
+        let cases: &[Case] = &[
+            (&[], (2560, 1440), (0, 0), all),
+            (
+                &["--anchor", "top"],
+                (2560, 1440),
+                (2560, 1440),
+                Anchor::TOP,
+            ),
+            (
+                &["--anchor", "top,bottom"],
+                (2560, 1440),
+                (2560, 0),
+                Anchor::TOP | Anchor::BOTTOM,
+            ),
+            // the output's size isnt known yet
+            (&["--anchor", "top"], (0, 0), (0, 0), all),
+            (&["--anchor", "left,right"], (0, 0), (0, 0), all),
+            (
+                &["--geometry", "800x600+10+20"],
+                (0, 0),
+                (800, 600),
+                Anchor::TOP | Anchor::LEFT,
+            ),
+        ];
+        for &(args, output_size, size, anchor) in cases {
+            let options = load(args, "");
+            let layer_size = options.layer_size(output_size);
+            assert_eq!(layer_size, size, "{:?} on {:?}", args, output_size);
+            assert_eq!(
+                options.layer_anchor(layer_size),
+                anchor,
+                "{:?} on {:?}",
+                args,
+                output_size
+            );
+        }
+    }
+
+    #[test]
+    fn seeds_exact_as_f32() {
+        assert_eq!(parse_seed("0"), Ok(Seed(0)));
+        assert_eq!(parse_seed("16777216"), Ok(Seed(16777216)));
+        assert!(parse_seed("16777217").is_err());
+        assert!(parse_seed("-1").is_err());
+        assert!(parse_seed("1.5").is_err());
+
+        assert_eq!(load(&[], "seed = 42").seed, Some(Seed(42)));
+        assert!(toml::from_str::<Options>("seed = 16777217").is_err());
+    }
+
+    #[test]
+    fn uniforms_declared_twice() {
+        let options = |providers: &[Provider], uniforms: &[&str]| Options {
+            providers: Some(providers.to_vec()),
+            uniforms: uniforms
+                .iter()
+                .map(|name| (name.to_string(), 0.0))
+                .collect(),
+            ..Options::default()
+        };
+
+        let cases = [
+            (options(&[Provider::CpuLoad], &["exposure"]), true),
+            (
+                options(&[Provider::CpuLoad], &["cpu_loadx", "cpu_load_x"]),
+                true,
+            ),
+            (options(&[Provider::CpuLoad, Provider::CpuLoad], &[]), false),
+            (options(&[Provider::CpuLoad], &["cpu_load"]), false),
+            (options(&[Provider::CpuLoad], &["cpu_load_1"]), false),
+        ];
+        for (options, ok) in cases {
+            assert_eq!(
+                options.check_uniforms().is_ok(),
+                ok,
+                "{:?}",
+                options.uniforms
+            );
+        }
+    }
+}
@@ -0,0 +1,193 @@
+//! Unix socket for driving a running instance, e.g. `echo pause | socat - UNIX:/path/to/socket`.
+//!
+//! Each line written to the socket is one command:
+//!
+//! - `shader <path>` switches every output to the given shader
+//! - `reload` re-reads the current shader from disk
+//! - `pause` / `resume` freeze and unfreeze rendering and time
+//! - `fps <n>` changes the frame rate, 0 for one frame per frame callback
+//! - `seek <secs>` jumps the shader's clock to the given time
+//! - `set <name> <value>` updates a `--uniform` or `@param` value
+
+use std::{
+    fs,
+    io::{self, Read, Write},
+    os::unix::{
+        fs::FileTypeExt,
+        net::{UnixListener, UnixStream},
+    },
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+use anyhow::{anyhow, bail, Context, Result};
+use log::{error, warn};
+use sctk::reexports::calloop::{generic::Generic, Interest, LoopHandle, Mode, PostAction};
+
+#[derive(Debug)]
+pub enum Command {
+    Shader(PathBuf),
+    Reload,
+    Pause,
+    Resume,
+    Fps(u32),
+    Seek(f32),
+    Set(String, f32),
+}
+
+impl FromStr for Command {
+    type Err = anyhow::Error;
+
+    fn from_str(line: &str) -> Result<Self> {
+        let line = line.trim();
+        let (name, arg) = match line.split_once(char::is_whitespace) {
+            Some((name, arg)) => (name, arg.trim()),
+            None => (line, ""),
+        };
+
+        match (name, arg) {
+            ("shader", path) if !path.is_empty() => Ok(Command::Shader(path.into())),
+            ("reload", "") => Ok(Command::Reload),
+            ("pause", "") => Ok(Command::Pause),
+            ("resume", "") => Ok(Command::Resume),
+            ("fps", fps) => Ok(Command::Fps(
+                fps.parse()
+                    .with_context(|| format!("invalid fps {:?}", fps))?,
+            )),
+            ("seek", secs) => Ok(Command::Seek(
+                secs.parse()
+                    .with_context(|| format!("invalid time {:?}", secs))?,
+            )),
+            ("set", arg) => {
+                let (uniform, value) = arg
+                    .split_once(char::is_whitespace)
+                    .ok_or_else(|| anyhow!("usage: set <name> <value>"))?;
+                let value = value.trim();
+                Ok(Command::Set(
+                    uniform.to_string(),
+                    value
+                        .parse()
+                        .with_context(|| format!("invalid value {:?}", value))?,
+                ))
+            }
+            _ => bail!("unknown command {:?}", line),
+        }
+    }
+}
+
+type Handler<D> = fn(&mut D, Command) -> Result<()>;
+
+/// Binds a socket at `path` and calls `handler` for every command received on it.
+pub fn listen<'l, D: 'l>(
+    path: &Path,
+    handle: LoopHandle<'l, D>,
+    handler: Handler<D>,
+) -> Result<()> {
+    // a socket left behind by a previous run would make bind fail. only one that nothing is
+    // listening on is removed, so another instance's socket or some other file is left alone
+    if let Ok(metadata) = fs::symlink_metadata(path) {
+        if !metadata.file_type().is_socket() {
+            bail!(
+                "control socket {} exists and is not a socket",
+                path.display()
+            );
+        }
+        match UnixStream::connect(path) {
+            Err(e) if e.kind() == io::ErrorKind::ConnectionRefused => fs::remove_file(path)
+                .with_context(|| format!("couldnt remove stale socket {}", path.display()))?,
+            Err(e) => bail!("control socket {} is unusable: {}", path.display(), e),
+            Ok(_) => bail!("control socket {} is in use", path.display()),
+        }
+    }
+
+    let listener = UnixListener::bind(path)
+        .with_context(|| format!("couldnt bind control socket {}", path.display()))?;
+    listener.set_nonblocking(true)?;
+
+    let clients = handle.clone();
+    handle
+        .insert_source(
+            Generic::new(listener, Interest::READ, Mode::Level),
+            move |_, listener, _| {
+                loop {
+                    match listener.accept() {
+                        Ok((stream, _)) => {
+                            if let Err(e) = insert_client(&clients, stream, handler) {
+                                warn!("couldnt accept control client: {}", e);
+                            }
+                        }
+                        Err(e) if e.kind() == io::ErrorKind::WouldBlock => break,
+                        Err(e) => {
+                            error!("control socket failed: {}", e);
+                            return Ok(PostAction::Remove);
+                        }
+                    }
+                }
+
+                Ok(PostAction::Continue)
+            },
+        )
+        .map_err(|e| anyhow!("couldnt listen on control socket: {}", e.error))?;
+
+    Ok(())
+}
+
+fn insert_client<'l, D: 'l>(
+    handle: &LoopHandle<'l, D>,
+    stream: UnixStream,
+    handler: Handler<D>,
+) -> Result<()> {
+    stream.set_nonblocking(true)?;
+
+    let mut pending = Vec::new();
+    handle
+        .insert_source(
+            Generic::new(stream, Interest::READ, Mode::Level),
+            move |_, stream, data| {
+                let mut closed = false;
+                let mut chunk = [0; 1024];
+                loop {
+                    match stream.read(&mut chunk) {
+                        Ok(0) => {
+                            closed = true;
+                            break;
+                        }
+                        Ok(n) => pending.extend_from_slice(&chunk[..n]),
+                        Err(e) if e.kind() == io::ErrorKind::WouldBlock => break,
+                        Err(_) => return Ok(PostAction::Remove),
+                    }
+                }
+
+                // a client that closes without a trailing newline still gets its last command run
+                if closed && !pending.is_empty() {
+                    pending.push(b'\n');
+                }
+
+                while let Some(end) = pending.iter().position(|&b| b == b'\n') {
+                    let line: Vec<u8> = pending.drain(..=end).collect();
+                    let line = String::from_utf8_lossy(&line);
+                    if line.trim().is_empty() {
+                        continue;
+                    }
+
+                    let result = line
+                        .parse::<Command>()
+                        .and_then(|command| handler(data, command));
+                    // the client may already be gone, nothing to do about it then
+                    let _ = match result {
+                        Ok(()) => writeln!(stream, "ok"),
+                        Err(e) => writeln!(stream, "error: {:#}", e),
+                    };
+                }
+
+                if closed {
+                    Ok(PostAction::Remove)
+                } else {
+                    Ok(PostAction::Continue)
+                }
+            },
+        )
+        .map_err(|e| anyhow!("{}", e.error))?;
+
+    Ok(())
+}
@@ -18,7 +18,7 @@ pub struct ListOutputs {
 
 impl ListOutputs {
     pub fn new(conn: &Connection) -> Result<Self> {
-        let (globals, mut event_queue) = registry_queue_init(&conn).unwrap();
+        let (globals, mut event_queue) = registry_queue_init(conn).unwrap();
 
         let qh = event_queue.handle();
         let mut list_outputs = ListOutputs {
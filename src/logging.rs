@@ -0,0 +1,26 @@
+//! Sets up the `log` backend. Events from glpaper are logged at info and up by default, everything
+//! else at warn, and `RUST_LOG` overrides both.
+
+use std::io::Write;
+
+use env_logger::{Builder, Env};
+
+use crate::config::LogFormat;
+
+pub fn init(format: LogFormat) {
+    let mut builder = Builder::from_env(Env::default().default_filter_or("warn,glpaper_rs=info"));
+
+    if format == LogFormat::Json {
+        builder.format(|buf, record| {
+            let line = serde_json::json!({
+                "time": buf.timestamp().to_string(),
+                "level": record.level().as_str(),
+                "target": record.target(),
+                "message": record.args().to_string(),
+            });
+            writeln!(buf, "{}", line)
+        });
+    }
+
+    builder.init();
+}
@@ -1,44 +1,86 @@
-use std::time::Duration;
+use std::{
+    collections::hash_map::RandomState,
+    fs,
+    hash::{BuildHasher, Hasher},
+    time::{Duration, Instant},
+};
 
-use anyhow::Result;
+use anyhow::{anyhow, bail, Context, Result};
 
-use raw_window_handle::{
-    HasRawDisplayHandle, HasRawWindowHandle, RawDisplayHandle, RawWindowHandle,
-    WaylandDisplayHandle, WaylandWindowHandle,
-};
-use renderer::{output_surface::OutputSurface, renderable::RenderConfig};
+use config::{Action, LogFormat, Options, Placement, Seed};
+use control::Command;
+use log::{error, info, warn};
+use renderer::{adapter, output_surface::OutputSurface, shader, validate};
 use sctk::{
     compositor::{CompositorHandler, CompositorState},
-    delegate_compositor, delegate_layer, delegate_output, delegate_registry, delegate_seat,
-    output::{OutputHandler, OutputState},
-    reexports::calloop::EventLoop,
+    delegate_compositor, delegate_layer, delegate_output, delegate_pointer, delegate_registry,
+    delegate_seat,
+    output::{OutputHandler, OutputInfo, OutputState},
+    reexports::calloop::{
+        signals::{Signal, Signals},
+        EventLoop,
+    },
     registry::{ProvidesRegistryState, RegistryState},
     registry_handlers,
-    seat::{Capability, SeatHandler, SeatState},
+    seat::{
+        pointer::{PointerEvent, PointerHandler},
+        Capability, SeatHandler, SeatState,
+    },
     shell::{
         wlr_layer::{
-            Anchor, KeyboardInteractivity, Layer, LayerShell, LayerShellHandler, LayerSurface,
-            LayerSurfaceConfigure,
+            LayerShell, LayerShellHandler, LayerSurface, LayerSurfaceConfigure,
         },
         WaylandSurface,
     },
 };
 use wayland_client::{
     globals::registry_queue_init,
-    protocol::{wl_output, wl_seat, wl_surface},
-    Connection, Proxy, QueueHandle, WaylandSource,
+    protocol::{wl_output, wl_pointer, wl_seat, wl_surface},
+    Connection, QueueHandle, WaylandSource,
 };
 
+mod config;
+mod control;
 mod handlers;
+mod logging;
+mod power;
 mod renderer;
 
 use crate::handlers::list_outputs::ListOutputs;
 
 fn main() -> Result<()> {
-    env_logger::init();
+    let options = Options::load()?;
+    logging::init(options.log_format.unwrap_or(LogFormat::Text));
+
+    if options.list_gpus {
+        let backends = options.backends();
+        let instance = wgpu::Instance::new(wgpu::InstanceDescriptor {
+            backends,
+            ..Default::default()
+        });
+        adapter::list_adapters(&instance, backends);
+        return Ok(());
+    }
+
+    if options.list_examples {
+        for (name, _) in shader::EXAMPLES {
+            println!("{}", name);
+        }
+        return Ok(());
+    }
+
+    if let Some(Action::Validate { shader: path }) = &options.action {
+        validate::validate(path, &options)?;
+        println!("{}: ok", path.display());
+        return Ok(());
+    }
+
+    // this masks the signals for the thread it's created on, which has to happen before wgpu or
+    // anything else starts threads of its own or those would still be killed by them
+    let signals = Signals::new(&[Signal::SIGTERM, Signal::SIGINT])?;
 
     // first get connection to wayland
-    let conn = Connection::connect_to_env().unwrap();
+    let conn = connect()?;
 
     // set up output listing handler
     // TODO: can we combine this with our existing handler? does it leak anything when we just
@@ -47,80 +89,89 @@ fn main() -> Result<()> {
     let outputs = list_outputs.output_state();
 
     // now set up main handler
-    let (globals, mut event_queue) = registry_queue_init(&conn).unwrap();
+    let (globals, mut event_queue) =
+        registry_queue_init(&conn).context("couldnt list the compositor's globals")?;
     let qh = event_queue.handle();
 
     let compositor_state = CompositorState::bind(&globals, &qh)?;
-    let layer_shell = LayerShell::bind(&globals, &qh)?;
+    let layer_shell = LayerShell::bind(&globals, &qh).context(
+        "the compositor doesn't support wlr-layer-shell (zwlr_layer_shell_v1), which glpaper needs \
+         to put the wallpaper behind windows. sway, Hyprland, river and KDE Plasma have it, GNOME \
+         doesn't",
+    )?;
+
+    // outputs are configured one after another, so without a shared start their clocks drift apart
+    let epoch = options.sync_outputs.then(Instant::now);
+    let seed = options.seed.map_or_else(random_seed, |seed| seed.0);
+    info!("seed {}, pass --seed {} to get the same look again", seed, seed);
+
+    let mut output_list: Vec<_> = outputs
+        .outputs()
+        .filter_map(|output| match outputs.info(&output) {
+            Some(info) => Some((output, info)),
+            // can happen to an output plugged in or out just now
+            None => {
+                warn!("skipping an output that hasnt said what it is yet");
+                None
+            }
+        })
+        .filter(|(_, info)| options.wants_output(info))
+        .collect();
+    if output_list.is_empty() {
+        warn!("no outputs to draw on");
+    }
+    output_list.sort_by(|(_, a), (_, b)| a.name.cmp(&b.name));
 
-    let output_surfaces: Vec<OutputSurface> = outputs.outputs().map(|output| {
+    let layers: Vec<_> = output_list.into_iter().map(|(output, output_info)| {
         let surface = compositor_state.create_surface(&qh);
         let layer =
-            layer_shell.create_layer_surface(&qh, surface, Layer::Background, Some("glpaper-rs"), Some(&output));
-        layer.set_size(123, 123);
-        layer.set_anchor(Anchor::TOP | Anchor::LEFT);
-        layer.set_keyboard_interactivity(KeyboardInteractivity::None);
-        layer.commit();
-
-        // Initialize wgpu
-        let instance = wgpu::Instance::new(wgpu::InstanceDescriptor {
-            backends: wgpu::Backends::all(),
-            ..Default::default()
+            layer_shell.create_layer_surface(&qh, surface, options.layer(), Some("glpaper-rs"), Some(&output));
+        let logical_size = logical_size(&output_info).unwrap_or_else(|| {
+            warn!("{}: output has no size yet, waiting for the compositor to pick one", output_info.name.as_deref().unwrap_or("unnamed output"));
+            (0, 0)
         });
+        let (width, height) = options.layer_size(logical_size);
+        layer.set_size(width, height);
+        layer.set_anchor(options.layer_anchor((width, height)));
+        let [top, right, bottom, left] = options.margin();
+        layer.set_margin(top, right, bottom, left);
+        layer.set_keyboard_interactivity(options.keyboard_interactivity());
+        if let Some(zone) = options.exclusive_zone {
+            layer.set_exclusive_zone(zone);
+        }
+        layer.commit();
 
-        // Create the raw window handle for the surface.
-        let handle = {
-            let mut handle = WaylandDisplayHandle::empty();
-            handle.display = conn.backend().display_ptr() as *mut _;
-            let display_handle = RawDisplayHandle::Wayland(handle);
-
-            let mut handle = WaylandWindowHandle::empty();
-            handle.surface = layer.wl_surface().id().as_ptr() as *mut _;
-            let window_handle = RawWindowHandle::Wayland(handle);
-
-            /// https://github.com/rust-windowing/raw-window-handle/issues/49
-            struct YesRawWindowHandleImplementingHasRawWindowHandleIsUnsound(
-                RawDisplayHandle,
-                RawWindowHandle,
-            );
-
-            unsafe impl HasRawDisplayHandle for YesRawWindowHandleImplementingHasRawWindowHandleIsUnsound {
-                fn raw_display_handle(&self) -> RawDisplayHandle {
-                    self.0
-                }
-            }
+        (output_info, layer)
+    }).collect();
 
-            unsafe impl HasRawWindowHandle for YesRawWindowHandleImplementingHasRawWindowHandleIsUnsound {
-                fn raw_window_handle(&self) -> RawWindowHandle {
-                    self.1
-                }
+    let mirrored = if options.mirror && layers.len() > 1 {
+        match mirror_outputs(&options, &layers, epoch, seed) {
+            Ok(output_surface) => Some(output_surface),
+            Err(e) => {
+                warn!("{:#}, drawing each output on its own", e);
+                None
             }
-
-            YesRawWindowHandleImplementingHasRawWindowHandleIsUnsound(display_handle, window_handle)
-        };
-
-        let surface = unsafe { instance.create_surface(&handle).unwrap() };
-
-        // Pick a supported adapter
-        let adapter = pollster::block_on(instance.request_adapter(&wgpu::RequestAdapterOptions {
-            compatible_surface: Some(&surface),
-            ..Default::default()
-        }))
-        .expect("couldnt get the surface");
-
-        let output_info = outputs.info(&output).expect("output has no info");
-
-        let (device, queue) = pollster::block_on(adapter.request_device(&Default::default(), None)).expect("couldnt get device");
-
-        OutputSurface::new(
-            output_info,
-            layer,
-            device,
-            surface,
-            adapter,
-            queue,
-        )
-    }).collect();
+        }
+    } else {
+        None
+    };
+    let output_surfaces = match mirrored {
+        Some(output_surface) => vec![output_surface],
+        None => {
+            let placements = placements(layers.iter().map(|(info, _)| info));
+            layers
+                .into_iter()
+                .zip(placements)
+                .map(|((output_info, layer), placement)| {
+                    let mut settings = options.for_output(Some(&output_info));
+                    settings.epoch = epoch;
+                    settings.seed = seed;
+                    settings.placement = placement;
+                    OutputSurface::new(output_info, layer, Vec::new(), settings)
+                })
+                .collect::<Result<Vec<_>>>()?
+        }
+    };
 
     // construct background_layer, then event loop so we can trigger rendering over time without depending on
     // messages coming in from wayland
@@ -132,6 +183,7 @@ fn main() -> Result<()> {
         output_state: OutputState::new(&globals, &qh),
 
         exit: false,
+        pointer: None,
         output_surfaces,
     };
 
@@ -143,50 +195,277 @@ fn main() -> Result<()> {
     let loop_handle = event_loop.handle();
     WaylandSource::new(event_queue)
         .unwrap()
-        .insert(loop_handle)
+        .insert(loop_handle.clone())
         .unwrap();
 
+    loop_handle
+        .insert_source(signals, |event, _, background_layer| {
+            info!("got {:?}, exiting", event.signal());
+            background_layer.exit = true;
+        })
+        .map_err(|e| anyhow!("couldnt listen for signals: {}", e.error))?;
+
+    if let Some(path) = &options.control_socket {
+        control::listen(path, loop_handle.clone(), BackgroundLayer::handle_command)?;
+    }
+
+    if options.power_uniform {
+        power::watch(loop_handle, BackgroundLayer::set_power)?;
+    }
+
+    let benchmark_end = options
+        .benchmark
+        .map(|secs| Instant::now() + Duration::from_secs_f64(secs));
+    let mut frame_times = Vec::new();
+
     // We don't draw immediately, the configure will notify us when to first draw.
     loop {
+        let timeout = background_layer
+            .output_surfaces
+            .iter()
+            .filter_map(|os| os.next_frame_in())
+            .min();
         event_loop
-            .dispatch(Duration::from_millis(10), &mut background_layer)
+            .dispatch(timeout, &mut background_layer)
             .unwrap();
         //event_queue.blocking_dispatch(&mut background_layer).unwrap();
 
         for os in background_layer.output_surfaces.iter_mut() {
-            match os.render() {
+            match os.render(&qh) {
+                Ok(true) if benchmark_end.is_some() => frame_times.extend(os.frame_time()),
                 Ok(_) => {}
                 Err(e) => {
-                    println!("{}", e)
+                    error!("{}: {:#}", os.name(), e);
+                    if let Err(e) = os.recover() {
+                        error!("{}: {:#}", os.name(), e);
+                    }
                 }
             };
         }
 
+        if benchmark_end.is_some_and(|end| Instant::now() >= end) {
+            print_benchmark(&frame_times, options.benchmark.unwrap_or_default());
+            background_layer.exit = true;
+        }
+
         if background_layer.exit {
-            println!("exiting example");
+            info!("exiting");
             break;
         }
     }
 
-    for output_surface in background_layer.output_surfaces.into_iter() {
-        drop(output_surface);
-        //drop(output_surface.surface);
-        //drop(output_surface.layer);
+    if let Some(path) = &options.control_socket {
+        let _ = fs::remove_file(path);
     }
 
+    background_layer.shutdown();
+
     Ok(())
 }
 
+/// Connects to the compositor, saying why that can't work where it's obvious, since running
+/// outside a Wayland session is the first thing people try.
+fn connect() -> Result<Connection> {
+    let display = std::env::var_os("WAYLAND_DISPLAY");
+    if display.is_none() && std::env::var_os("WAYLAND_SOCKET").is_none() {
+        bail!(
+            "glpaper requires a Wayland compositor supporting wlr-layer-shell; WAYLAND_DISPLAY not \
+             set"
+        );
+    }
+
+    Connection::connect_to_env().with_context(|| {
+        format!(
+            "glpaper requires a Wayland compositor supporting wlr-layer-shell; couldnt connect to \
+             WAYLAND_DISPLAY={:?}, is the compositor still running?",
+            display.unwrap_or_default()
+        )
+    })
+}
+
+/// A seed for a run without `--seed`. Small enough to be exact in the shader's `f32`.
+fn random_seed() -> u32 {
+    let random = RandomState::new().build_hasher().finish();
+    (random % Seed::MAX as u64) as u32
+}
+
+/// For `--mirror`, one surface drawing on the first of `layers` and showing the same frame on the
+/// rest. Fails if they're set to draw with different gpus, or the gpu can't present to them all.
+fn mirror_outputs(
+    options: &Options,
+    layers: &[(OutputInfo, LayerSurface)],
+    epoch: Option<Instant>,
+    seed: u32,
+) -> Result<OutputSurface> {
+    let (output_info, layer) = &layers[0];
+    let mut settings = options.for_output(Some(output_info));
+    if layers[1..]
+        .iter()
+        .any(|(info, _)| options.for_output(Some(info)).adapter != settings.adapter)
+    {
+        bail!("cant mirror outputs set to draw with different gpus");
+    }
+    settings.epoch = epoch;
+    settings.seed = seed;
+    // the picture is the same everywhere, so it's laid out as if this was the only output
+    settings.placement = placements(std::iter::once(output_info)).remove(0);
+
+    OutputSurface::new(
+        output_info.clone(),
+        layer.clone(),
+        layers[1..].to_vec(),
+        settings,
+    )
+    .context("couldnt mirror outputs")
+}
+
+/// Where each of `outputs` sits in the box around all of them, in the order given.
+fn placements<'a>(outputs: impl Iterator<Item = &'a OutputInfo> + Clone) -> Vec<Placement> {
+    let rect = |info: &OutputInfo| {
+        let (x, y) = info.logical_position.unwrap_or_default();
+        let (width, height) = logical_size(info).unwrap_or_default();
+        (x, y, x + width, y + height)
+    };
+
+    let (left, top, right, bottom) = outputs.clone().map(rect).fold(
+        (i32::MAX, i32::MAX, i32::MIN, i32::MIN),
+        |(left, top, right, bottom), (x0, y0, x1, y1)| {
+            (left.min(x0), top.min(y0), right.max(x1), bottom.max(y1))
+        },
+    );
+
+    let count = outputs.clone().count() as u32;
+    outputs
+        .map(rect)
+        .enumerate()
+        .map(|(index, (x0, _, _, y1))| Placement {
+            index: index as u32,
+            count,
+            // flipped so y goes up, like frag_coord
+            offset: [(x0 - left) as f32, (bottom - y1) as f32],
+            virtual_size: [(right - left) as f32, (bottom - top) as f32],
+        })
+        .collect()
+}
+
+/// The output's size in surface coordinates. Outputs can leave `logical_size` unset for a while when
+/// plugged in, in which case it's worked out from the current mode.
+fn logical_size(info: &OutputInfo) -> Option<(i32, i32)> {
+    if info.logical_size.is_some() {
+        return info.logical_size;
+    }
+
+    let mode = info.modes.iter().find(|mode| mode.current)?;
+    Some(mode_size(
+        mode.dimensions,
+        info.scale_factor,
+        info.transform,
+    ))
+}
+
+/// The size in surface coordinates of a mode of `dimensions` pixels, shown at `scale` and turned
+/// by `transform`.
+fn mode_size(
+    (width, height): (i32, i32),
+    scale: i32,
+    transform: wl_output::Transform,
+) -> (i32, i32) {
+    let scale = scale.max(1);
+    let (width, height) = (width / scale, height / scale);
+    let rotated = matches!(
+        transform,
+        wl_output::Transform::_90
+            | wl_output::Transform::_270
+            | wl_output::Transform::Flipped90
+            | wl_output::Transform::Flipped270
+    );
+
+    if rotated {
+        (height, width)
+    } else {
+        (width, height)
+    }
+}
+
+fn print_benchmark(frame_times: &[f32], secs: f64) {
+    if frame_times.is_empty() {
+        println!("benchmark: no frames presented in {}s", secs);
+        return;
+    }
+
+    let min = frame_times.iter().copied().fold(f32::INFINITY, f32::min);
+    let max = frame_times.iter().copied().fold(0.0, f32::max);
+    let avg = frame_times.iter().sum::<f32>() / frame_times.len() as f32;
+
+    println!(
+        "benchmark: {} frames in {}s ({:.1} fps), frame time min {:.2}ms avg {:.2}ms max {:.2}ms",
+        frame_times.len(),
+        secs,
+        frame_times.len() as f64 / secs,
+        min * 1000.0,
+        avg * 1000.0,
+        max * 1000.0,
+    );
+}
+
 struct BackgroundLayer {
     registry_state: RegistryState,
     seat_state: SeatState,
     output_state: OutputState,
 
     exit: bool,
+    pointer: Option<wl_pointer::WlPointer>,
 
     output_surfaces: Vec<OutputSurface>,
 }
 
+impl BackgroundLayer {
+    /// Tears down every output, waiting for each gpu to finish before its resources go.
+    fn shutdown(&mut self) {
+        self.output_surfaces.clear();
+    }
+
+    fn set_power(&mut self, power: [f32; 2]) {
+        for output_surface in self.output_surfaces.iter_mut() {
+            output_surface.set_power(power);
+        }
+    }
+
+    /// Applies `command` to every output, even if it fails on some of them, reporting all of
+    /// their errors at once.
+    fn handle_command(&mut self, command: Command) -> Result<()> {
+        let mut errors = Vec::new();
+        for output_surface in self.output_surfaces.iter_mut() {
+            // the same mistake on every output only needs saying once
+            if let Err(e) = apply_command(output_surface, &command) {
+                let e = format!("{:#}", e);
+                if !errors.contains(&e) {
+                    errors.push(e);
+                }
+            }
+        }
+
+        if !errors.is_empty() {
+            bail!("{}", errors.join("; "));
+        }
+        Ok(())
+    }
+}
+
+fn apply_command(output_surface: &mut OutputSurface, command: &Command) -> Result<()> {
+    match *command {
+        Command::Shader(ref path) => output_surface.set_shader(Some(path.clone()))?,
+        Command::Reload => output_surface.reload()?,
+        Command::Pause => output_surface.set_paused(true),
+        Command::Resume => output_surface.set_paused(false),
+        Command::Fps(fps) => output_surface.set_fps(fps),
+        Command::Seek(secs) => output_surface.seek(secs),
+        Command::Set(ref name, value) => output_surface.set_uniform(name, value)?,
+    }
+
+    Ok(())
+}
+
 impl CompositorHandler for BackgroundLayer {
     fn scale_factor_changed(
         &mut self,
@@ -212,9 +491,15 @@ impl CompositorHandler for BackgroundLayer {
         &mut self,
         _conn: &Connection,
         _qh: &QueueHandle<Self>,
-        _surface: &wl_surface::WlSurface,
+        surface: &wl_surface::WlSurface,
         _time: u32,
     ) {
+        for output_surface in self.output_surfaces.iter_mut() {
+            if output_surface.surface_matches(surface) {
+                output_surface.frame_done();
+            }
+            output_surface.mirror_frame_done(surface);
+        }
     }
 }
 
@@ -235,8 +520,15 @@ impl OutputHandler for BackgroundLayer {
         &mut self,
         _conn: &Connection,
         _qh: &QueueHandle<Self>,
-        _output: wl_output::WlOutput,
+        output: wl_output::WlOutput,
     ) {
+        if let Some(info) = self.output_state.info(&output) {
+            for output_surface in self.output_surfaces.iter_mut() {
+                if output_surface.output_matches(&info) {
+                    output_surface.update_output(info.clone());
+                }
+            }
+        }
     }
 
     fn output_destroyed(
@@ -252,12 +544,17 @@ impl LayerShellHandler for BackgroundLayer {
     fn configure(
         &mut self,
         _: &Connection,
-        _: &QueueHandle<Self>,
+        qh: &QueueHandle<Self>,
         this_layer: &LayerSurface,
-        _: LayerSurfaceConfigure,
+        configure: LayerSurfaceConfigure,
         _: u32,
     ) {
+        let (width, height) = configure.new_size;
         for output_surface in self.output_surfaces.iter_mut() {
+            // drawn along with the output it mirrors
+            if output_surface.resize_mirror(this_layer, width, height) {
+                continue;
+            }
             if !output_surface.layer_matches(this_layer) {
                 continue;
             }
@@ -267,23 +564,40 @@ impl LayerShellHandler for BackgroundLayer {
             //    .surface
             //    .get_capabilities(&output_surface.adapter);
 
-            let config = RenderConfig::new(
-                output_surface,
-                "fn main_image(frag_color: vec4<f32>, frag_coord: vec2<f32>) -> vec4<f32> {
-    let uv = frag_coord / u.resolution;
-    let color = 0.5 + 0.5 * cos(u.time + uv.xyx + vec3(0.0, 2.0, 4.0));
-    return vec4(color, 1.0);
-}",
-            )
-            .unwrap();
+            output_surface.resize(width, height);
+
+            // nothing to draw on until a configure comes with a size
+            if !output_surface.has_size() {
+                continue;
+            }
+
+            if !output_surface.has_pipeline() {
+                if let Err(e) = output_surface.prepare() {
+                    error!("{:#}", e);
+                    continue;
+                }
+            }
 
-            output_surface.prep_render_pipeline(&config).unwrap();
-            output_surface.render().unwrap();
+            if let Err(e) = output_surface.render(qh) {
+                error!("{}: {:#}", output_surface.name(), e);
+            }
         }
     }
 
-    fn closed(&mut self, _: &Connection, _: &QueueHandle<Self>, _: &LayerSurface) {
-        todo!()
+    /// The compositor won't show this layer any more, usually because its output went away, so
+    /// the output's surface is torn down and the rest keep going. With `--mirror` the outputs
+    /// mirroring it go blank along with it.
+    fn closed(&mut self, _: &Connection, _: &QueueHandle<Self>, closed_layer: &LayerSurface) {
+        for output_surface in self.output_surfaces.iter_mut() {
+            output_surface.close_mirror(closed_layer);
+        }
+        self.output_surfaces.retain(|output_surface| {
+            let closed = output_surface.layer_matches(closed_layer);
+            if closed {
+                info!("{}: layer closed by the compositor", output_surface.name());
+            }
+            !closed
+        });
     }
 }
 
@@ -297,10 +611,16 @@ impl SeatHandler for BackgroundLayer {
     fn new_capability(
         &mut self,
         _conn: &Connection,
-        _qh: &QueueHandle<Self>,
-        _seat: wl_seat::WlSeat,
-        _capability: Capability,
+        qh: &QueueHandle<Self>,
+        seat: wl_seat::WlSeat,
+        capability: Capability,
     ) {
+        if capability == Capability::Pointer && self.pointer.is_none() {
+            match self.seat_state.get_pointer(qh, &seat) {
+                Ok(pointer) => self.pointer = Some(pointer),
+                Err(e) => warn!("couldnt get pointer: {}", e),
+            }
+        }
     }
 
     fn remove_capability(
@@ -308,8 +628,13 @@ impl SeatHandler for BackgroundLayer {
         _conn: &Connection,
         _: &QueueHandle<Self>,
         _: wl_seat::WlSeat,
-        _capability: Capability,
+        capability: Capability,
     ) {
+        if capability == Capability::Pointer {
+            if let Some(pointer) = self.pointer.take() {
+                pointer.release();
+            }
+        }
     }
 
     fn remove_seat(&mut self, _: &Connection, _: &QueueHandle<Self>, _: wl_seat::WlSeat) {}
@@ -318,7 +643,26 @@ impl SeatHandler for BackgroundLayer {
 delegate_compositor!(BackgroundLayer);
 delegate_output!(BackgroundLayer);
 
+impl PointerHandler for BackgroundLayer {
+    fn pointer_frame(
+        &mut self,
+        _conn: &Connection,
+        _qh: &QueueHandle<Self>,
+        _pointer: &wl_pointer::WlPointer,
+        events: &[PointerEvent],
+    ) {
+        for event in events {
+            for output_surface in self.output_surfaces.iter_mut() {
+                if output_surface.surface_matches(&event.surface) {
+                    output_surface.set_mouse(event);
+                }
+            }
+        }
+    }
+}
+
 delegate_seat!(BackgroundLayer);
+delegate_pointer!(BackgroundLayer);
 
 delegate_layer!(BackgroundLayer);
 
@@ -328,5 +672,35 @@ impl ProvidesRegistryState for BackgroundLayer {
     fn registry(&mut self) -> &mut RegistryState {
         &mut self.registry_state
     }
-    registry_handlers![OutputState];
+    registry_handlers![OutputState, SeatState];
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn mode_sizes() {
+        use wl_output::Transform;
+
+        let cases = [
+            ((2560, 1440), 1, Transform::Normal, (2560, 1440)),
+            ((3840, 2160), 2, Transform::Normal, (1920, 1080)),
+            ((3840, 2160), 2, Transform::Flipped, (1920, 1080)),
+            ((2560, 1440), 1, Transform::_90, (1440, 2560)),
+            ((3840, 2160), 2, Transform::Flipped270, (1080, 1920)),
+            // an output that hasnt sent its scale yet
+            ((1920, 1080), 0, Transform::_180, (1920, 1080)),
+        ];
+        for (dimensions, scale, transform, size) in cases {
+            assert_eq!(
+                mode_size(dimensions, scale, transform),
+                size,
+                "{:?} at {} turned {:?}",
+                dimensions,
+                scale,
+                transform
+            );
+        }
+    }
 }
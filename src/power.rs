@@ -0,0 +1,71 @@
+//! Battery state for `--power-uniform`, read from `/sys/class/power_supply` on a slow timer.
+
+use std::{fs, path::Path, time::Duration};
+
+use anyhow::{anyhow, Result};
+use sctk::reexports::calloop::{
+    timer::{TimeoutAction, Timer},
+    LoopHandle,
+};
+
+const POWER_SUPPLY: &str = "/sys/class/power_supply";
+
+/// Battery charge changes slowly, there's no point reading it more often.
+const POLL_INTERVAL: Duration = Duration::from_secs(30);
+
+type Handler<D> = fn(&mut D, [f32; 2]);
+
+/// Calls `handler` with the power state (see [`read`]) straight away and then every
+/// [`POLL_INTERVAL`].
+pub fn watch<'l, D: 'l>(handle: LoopHandle<'l, D>, handler: Handler<D>) -> Result<()> {
+    handle
+        .insert_source(Timer::immediate(), move |_, _, data| {
+            handler(data, read());
+            TimeoutAction::ToDuration(POLL_INTERVAL)
+        })
+        .map_err(|e| anyhow!("couldnt start power timer: {}", e.error))?;
+
+    Ok(())
+}
+
+/// `[charge, plugged]`: the charge of all batteries from 0 to 1, and 1 when on external power,
+/// otherwise 0. Machines without a battery always report `[1, 1]`.
+pub fn read() -> [f32; 2] {
+    let supplies = match fs::read_dir(POWER_SUPPLY) {
+        Ok(entries) => entries,
+        Err(_) => return [1.0, 1.0],
+    };
+
+    let mut capacities = Vec::new();
+    let mut plugged = false;
+    for supply in supplies.flatten() {
+        let path = supply.path();
+        match read_attribute(&path, "type").as_deref() {
+            Some("Battery") => {
+                capacities.extend(
+                    read_attribute(&path, "capacity").and_then(|capacity| capacity.parse::<f32>().ok()),
+                );
+                // a full battery on the charger isn't charging any more, but is still plugged in
+                plugged |= matches!(
+                    read_attribute(&path, "status").as_deref(),
+                    Some("Charging" | "Full" | "Not charging")
+                );
+            }
+            Some("Mains") => plugged |= read_attribute(&path, "online").as_deref() == Some("1"),
+            _ => {}
+        }
+    }
+
+    if capacities.is_empty() {
+        return [1.0, 1.0];
+    }
+
+    let charge = capacities.iter().sum::<f32>() / capacities.len() as f32 / 100.0;
+    [charge.clamp(0.0, 1.0), if plugged { 1.0 } else { 0.0 }]
+}
+
+fn read_attribute(supply: &Path, name: &str) -> Option<String> {
+    fs::read_to_string(supply.join(name))
+        .ok()
+        .map(|value| value.trim().to_string())
+}
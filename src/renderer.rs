@@ -1,2 +1,23 @@
+//! There is a single rendering path:
+//!
+//! - [`output_surface::OutputSurface`] owns the wgpu device, queue and surface for one output and
+//!   builds the render pipeline for it.
+//! - [`renderable::Renderable`] draws frames with that pipeline. Its [`renderable::RenderState`]
+//!   owns the [`renderable::Uniform`] struct, which is the only definition of the uniforms and must
+//!   match `Uniforms` in `assets/fragment.prefix.wgsl`.
+//! - With `--mirror`, one [`output_surface::OutputSurface`] draws for every output, and each
+//!   [`mirror::Mirror`] shows its frame on another one.
+//!
+//! [`validate`] builds the same pipeline offscreen to check a shader without a Wayland session.
+
+pub mod adapter;
+pub mod feedback;
+pub mod mirror;
 pub mod output_surface;
+pub mod pipe;
+pub mod precompute;
+pub mod provider;
 pub mod renderable;
+pub mod shader;
+pub mod upscale;
+pub mod validate;
@@ -0,0 +1,175 @@
+use anyhow::{anyhow, bail, Context, Result};
+use log::{info, warn};
+use raw_window_handle::{
+    HasRawDisplayHandle, HasRawWindowHandle, RawDisplayHandle, RawWindowHandle,
+    WaylandDisplayHandle, WaylandWindowHandle,
+};
+use wayland_client::{protocol::wl_surface::WlSurface, Proxy};
+use wgpu::{Adapter, Backends, Device, Instance, Queue, Surface};
+
+/// Which adapter to render with, from `--backend`, `--gpu` and `--force-fallback-adapter`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct AdapterChoice {
+    pub backends: Backends,
+    pub gpu: Option<String>,
+    pub force_fallback: bool,
+}
+
+/// Prints every adapter wgpu can see, for picking one with `--gpu`.
+pub fn list_adapters(instance: &Instance, backends: Backends) {
+    for adapter in instance.enumerate_adapters(backends) {
+        let info = adapter.get_info();
+        println!("{} ({:?}, {:?})", info.name, info.backend, info.device_type);
+    }
+}
+
+/// Prints what `adapter` and `surface` support, for `--dump-caps`.
+pub fn dump_caps(name: &str, adapter: &Adapter, surface: &Surface) {
+    let capabilities = surface.get_capabilities(adapter);
+    println!("{}:", name);
+    println!("  adapter: {:?}", adapter.get_info());
+    println!("  formats: {:?}", capabilities.formats);
+    println!("  present modes: {:?}", capabilities.present_modes);
+    println!("  alpha modes: {:?}", capabilities.alpha_modes);
+    println!("  features: {:?}", adapter.features());
+    println!("  limits: {:#?}", adapter.limits());
+}
+
+/// Picks the adapter to render `surface` with (or to render offscreen, without one): the first one
+/// whose name contains `choice.gpu`, or whatever wgpu prefers if no name was given. If no hardware
+/// adapter is available, or `choice.force_fallback` is set, wgpu's software adapter is used instead.
+pub fn select_adapter(
+    instance: &Instance,
+    surface: Option<&Surface>,
+    choice: &AdapterChoice,
+) -> Result<Adapter> {
+    let request = |force_fallback_adapter| {
+        pollster::block_on(instance.request_adapter(&wgpu::RequestAdapterOptions {
+            force_fallback_adapter,
+            compatible_surface: surface,
+            ..Default::default()
+        }))
+    };
+
+    if choice.force_fallback {
+        return request(true).ok_or_else(|| anyhow!("couldnt get a fallback adapter"));
+    }
+
+    let gpu = match choice.gpu.as_deref() {
+        Some(gpu) => gpu,
+        None => {
+            return request(false)
+                .or_else(|| {
+                    warn!("no hardware adapter found, falling back to software rendering");
+                    request(true)
+                })
+                .ok_or_else(|| anyhow!("couldnt find an adapter for the surface"))
+        }
+    };
+
+    let wanted = gpu.to_lowercase();
+    let matching: Vec<Adapter> = instance
+        .enumerate_adapters(choice.backends)
+        .filter(|adapter| adapter.get_info().name.to_lowercase().contains(&wanted))
+        .collect();
+
+    if matching.is_empty() {
+        bail!("no gpu matching {:?}, see --list-gpus", gpu);
+    }
+
+    matching
+        .into_iter()
+        .find(|adapter| surface.is_none_or(|surface| adapter.is_surface_supported(surface)))
+        .ok_or_else(|| anyhow!("gpu matching {:?} can't render to this output", gpu))
+}
+
+/// Creates a wgpu surface for `wl_surface` and each of `mirrors`, and a device to draw to all of
+/// them with. Fails if the adapter picked for `wl_surface` can't present to the mirrors. `name`
+/// is only used for logging.
+pub fn connect(
+    wl_surface: &WlSurface,
+    mirrors: &[&WlSurface],
+    name: &str,
+    choice: &AdapterChoice,
+) -> Result<(Surface, Vec<Surface>, Adapter, Device, Queue)> {
+    let instance = wgpu::Instance::new(wgpu::InstanceDescriptor {
+        backends: choice.backends,
+        ..Default::default()
+    });
+
+    let surface = create_surface(&instance, wl_surface)?;
+    let mirror_surfaces = mirrors
+        .iter()
+        .map(|mirror| create_surface(&instance, mirror))
+        .collect::<Result<Vec<_>>>()?;
+
+    let adapter = select_adapter(&instance, Some(&surface), choice)?;
+    let adapter_info = adapter.get_info();
+    if !mirror_surfaces
+        .iter()
+        .all(|mirror| adapter.is_surface_supported(mirror))
+    {
+        bail!(
+            "{} ({:?}) can't present to every output",
+            adapter_info.name,
+            adapter_info.backend
+        );
+    }
+    info!(
+        "{}: using {} ({:?})",
+        name, adapter_info.name, adapter_info.backend
+    );
+
+    // needed for --msaa sample counts other than 4
+    let features = adapter.features() & wgpu::Features::TEXTURE_ADAPTER_SPECIFIC_FORMAT_FEATURES;
+    let (device, queue) = pollster::block_on(adapter.request_device(
+        &wgpu::DeviceDescriptor {
+            features,
+            ..Default::default()
+        },
+        None,
+    ))
+    .context("couldnt get device")?;
+
+    Ok((surface, mirror_surfaces, adapter, device, queue))
+}
+
+/// Creates a wgpu surface on `instance` for `wl_surface`.
+fn create_surface(instance: &Instance, wl_surface: &WlSurface) -> Result<Surface> {
+    // Create the raw window handle for the surface.
+    let handle = {
+        let backend = wl_surface
+            .backend()
+            .upgrade()
+            .context("wayland connection is gone")?;
+        let mut handle = WaylandDisplayHandle::empty();
+        handle.display = backend.display_ptr() as *mut _;
+        let display_handle = RawDisplayHandle::Wayland(handle);
+
+        let mut handle = WaylandWindowHandle::empty();
+        handle.surface = wl_surface.id().as_ptr() as *mut _;
+        let window_handle = RawWindowHandle::Wayland(handle);
+
+        /// https://github.com/rust-windowing/raw-window-handle/issues/49
+        struct YesRawWindowHandleImplementingHasRawWindowHandleIsUnsound(
+            RawDisplayHandle,
+            RawWindowHandle,
+        );
+
+        unsafe impl HasRawDisplayHandle for YesRawWindowHandleImplementingHasRawWindowHandleIsUnsound {
+            fn raw_display_handle(&self) -> RawDisplayHandle {
+                self.0
+            }
+        }
+
+        unsafe impl HasRawWindowHandle for YesRawWindowHandleImplementingHasRawWindowHandleIsUnsound {
+            fn raw_window_handle(&self) -> RawWindowHandle {
+                self.1
+            }
+        }
+
+        YesRawWindowHandleImplementingHasRawWindowHandleIsUnsound(display_handle, window_handle)
+    };
+
+    Ok(unsafe { instance.create_surface(&handle) }?)
+}
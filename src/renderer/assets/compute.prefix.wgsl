@@ -0,0 +1,3 @@
+@group(0) @binding(0)
+var precomputed_storage: texture_storage_2d<rgba16float, write>;
+
@@ -0,0 +1,11 @@
+
+@compute @workgroup_size(8, 8)
+fn main(@builtin(global_invocation_id) id: vec3<u32>) {
+    let size = textureDimensions(precomputed_storage);
+    if id.x >= u32(size.x) || id.y >= u32(size.y) {
+        return;
+    }
+    // flipped to put the origin at the bottom left, and at the centre of the texel like frag_coord
+    let coord = vec2(f32(id.x), f32(size.y) - 1.0 - f32(id.y)) + 0.5;
+    textureStore(precomputed_storage, vec2<i32>(id.xy), precompute(coord, vec2<f32>(size)));
+}
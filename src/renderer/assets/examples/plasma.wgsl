@@ -0,0 +1,13 @@
+fn main_image(frag_color: vec4<f32>, frag_coord: vec2<f32>) -> vec4<f32> {
+    let uv = (frag_coord - 0.5 * u.resolution) / u.resolution.y * 10.0;
+    let t = u.time * 0.5;
+
+    var v = sin(uv.x + t);
+    v += sin((uv.y + t) * 0.5);
+    v += sin((uv.x + uv.y + t) * 0.5);
+    let c = uv + 5.0 * vec2(sin(t / 3.0), cos(t / 2.0));
+    v += sin(sqrt(dot(c, c) + 1.0) + t);
+
+    let color = 0.5 + 0.5 * sin(3.14159 * v + vec3(0.0, 2.094, 4.188));
+    return vec4(color, 1.0);
+}
@@ -0,0 +1,9 @@
+fn main_image(frag_color: vec4<f32>, frag_coord: vec2<f32>) -> vec4<f32> {
+    let uv = (frag_coord - 0.5 * u.resolution) / u.resolution.y;
+    let d = length(uv);
+
+    let rings = 0.5 + 0.5 * sin(40.0 * d - 4.0 * u.time);
+    let fade = exp(-3.0 * d);
+    let color = mix(vec3(0.05, 0.02, 0.1), vec3(0.9, 0.5, 0.2), rings * fade);
+    return vec4(color, 1.0);
+}
@@ -0,0 +1,11 @@
+@group(1) @binding(0)
+var previous_frame_texture: texture_2d<f32>;
+@group(1) @binding(1)
+var previous_frame_sampler: sampler;
+
+// what was drawn at uv last frame, with uv from 0 to 1 and the origin at the bottom left like
+// frag_coord
+fn previous_frame(uv: vec2<f32>) -> vec4<f32> {
+    return textureSample(previous_frame_texture, previous_frame_sampler, vec2(uv.x, 1.0 - uv.y));
+}
+
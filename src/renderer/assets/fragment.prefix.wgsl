@@ -5,6 +5,25 @@ struct Uniforms {
     mouse_release: vec2<f32>,
     resolution: vec2<f32>,
     time: f32,
+    frame_rate: f32,
+    frame: u32,
+    time_delta: f32,
+    // index of this output, and how many there are
+    output: vec2<u32>,
+    audio: vec4<f32>,
+    // where this output's bottom left corner is in the box around every output, and that box's
+    // size, for shaders spanning all outputs
+    output_offset: vec2<f32>,
+    virtual_resolution: vec2<f32>,
+    // battery charge from 0 to 1, and 1 when plugged in, with --power-uniform. [1, 1] without a
+    // battery or the flag
+    power: vec2<f32>,
+    // of this output in Hz, 0 if the compositor doesn't say
+    refresh_rate: f32,
+    // total mouse wheel movement over this output in steps, with up and right positive
+    scroll: vec2<f32>,
+    // --seed, or a random whole number picked at startup, the same on every output and frame
+    seed: f32,
 };
 
 @group(0) @binding(0)
@@ -1,8 +1,19 @@
 
 
+// for surfaces that don't encode to sRGB themselves, so colors look the same on every output
+fn linear_to_srgb(linear: vec3<f32>) -> vec3<f32> {
+    let c = max(linear, vec3(0.0));
+    let low = c * 12.92;
+    let high = 1.055 * pow(c, vec3(1.0 / 2.4)) - 0.055;
+    return select(high, low, c <= vec3(0.0031308));
+}
+
 @fragment
 fn main(@builtin(position) frag_coord: vec4<f32>) -> @location(0) vec4<f32> {
     let base_color = vec4(0.0, 0.0, 0.0, 1.0);
-    let color = main_image(base_color, ((frag_coord.xy - vec2(0.0, u.resolution.y)) * vec2(1.0, -1.0)));
-    return vec4(color.rgb, 1.0);
+    // flip to put the origin at the bottom left, the height drawn at is resolution / COORD_SCALE
+    let coord = vec2(frag_coord.x, u.resolution.y / COORD_SCALE - frag_coord.y) * COORD_SCALE;
+    let color = main_image(base_color, coord);
+    let rgb = select(color.rgb, linear_to_srgb(color.rgb), ENCODE_SRGB);
+    return vec4(rgb, select(1.0, color.a, TRANSPARENT));
 }
@@ -0,0 +1,6 @@
+// what --compute stored at uv, with uv from 0 to 1 and the origin at the bottom left like
+// frag_coord
+fn precomputed(uv: vec2<f32>) -> vec4<f32> {
+    return textureSample(precomputed_texture, precomputed_sampler, vec2(uv.x, 1.0 - uv.y));
+}
+
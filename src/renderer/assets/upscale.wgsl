@@ -0,0 +1,48 @@
+@group(0) @binding(0)
+var source: texture_2d<f32>;
+@group(0) @binding(1)
+var source_sampler: sampler;
+
+struct VertexOutput {
+    @builtin(position) position: vec4<f32>,
+    @location(0) uv: vec2<f32>,
+};
+
+// the point of the picture shown at `p` on the surface, both with the origin at the bottom left,
+// for --transform. TRANSFORM is prepended, in wl_output::Transform order
+fn untransform(p: vec2<f32>) -> vec2<f32> {
+    var c = p;
+    switch TRANSFORM % 4u {
+        case 1u: {
+            c = vec2(p.y, 1.0 - p.x);
+        }
+        case 2u: {
+            c = 1.0 - p;
+        }
+        case 3u: {
+            c = vec2(1.0 - p.y, p.x);
+        }
+        default: {}
+    }
+    if TRANSFORM >= 4u {
+        c.x = 1.0 - c.x;
+    }
+    return c;
+}
+
+@vertex
+fn vs_main(@builtin(vertex_index) in_vertex_index: u32) -> VertexOutput {
+    let x = f32(i32((in_vertex_index << 1u) & 2u));
+    let y = f32(i32(in_vertex_index & 2u));
+    var out: VertexOutput;
+    out.position = vec4(2.0 * vec2(x, y) - vec2(1.0), 0.0, 1.0);
+    // texture coordinates start at the top
+    let c = untransform(vec2(x, y));
+    out.uv = vec2(c.x, 1.0 - c.y);
+    return out;
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    return textureSample(source, source_sampler, in.uv);
+}
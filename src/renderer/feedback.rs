@@ -0,0 +1,134 @@
+use wgpu::{BindGroup, BindGroupLayout, CommandEncoder, Device, Texture, TextureFormat};
+
+/// Holds the previous frame for `--feedback`, so the shader can read what it drew last time.
+/// Starts out cleared to transparent black.
+pub struct Feedback {
+    format: TextureFormat,
+    bind_group_layout: BindGroupLayout,
+    sampler: wgpu::Sampler,
+
+    texture: Texture,
+    size: (u32, u32),
+    bind_group: BindGroup,
+}
+
+impl Feedback {
+    pub fn new(device: &Device, format: TextureFormat, size: (u32, u32)) -> Self {
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Feedback Bind Group Layout"),
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        multisampled: false,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                    count: None,
+                },
+            ],
+        });
+
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some("Feedback Sampler"),
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            ..Default::default()
+        });
+
+        let (texture, bind_group) =
+            create_previous(device, &bind_group_layout, &sampler, format, size);
+
+        Self {
+            format,
+            bind_group_layout,
+            sampler,
+            texture,
+            size,
+            bind_group,
+        }
+    }
+
+    pub fn bind_group_layout(&self) -> &BindGroupLayout {
+        &self.bind_group_layout
+    }
+
+    pub fn bind_group(&self) -> &BindGroup {
+        &self.bind_group
+    }
+
+    /// Starts over at the new size, the old frame can't be stretched to fit.
+    pub fn resize(&mut self, device: &Device, size: (u32, u32)) {
+        let (texture, bind_group) = create_previous(
+            device,
+            &self.bind_group_layout,
+            &self.sampler,
+            self.format,
+            size,
+        );
+        self.texture = texture;
+        self.size = size;
+        self.bind_group = bind_group;
+    }
+
+    /// Keeps `frame`, which must match this in size and format, for the next frame to read.
+    pub fn store(&self, encoder: &mut CommandEncoder, frame: &Texture) {
+        encoder.copy_texture_to_texture(
+            frame.as_image_copy(),
+            self.texture.as_image_copy(),
+            wgpu::Extent3d {
+                width: self.size.0,
+                height: self.size.1,
+                depth_or_array_layers: 1,
+            },
+        );
+    }
+}
+
+fn create_previous(
+    device: &Device,
+    layout: &BindGroupLayout,
+    sampler: &wgpu::Sampler,
+    format: TextureFormat,
+    (width, height): (u32, u32),
+) -> (Texture, BindGroup) {
+    let texture = device.create_texture(&wgpu::TextureDescriptor {
+        label: Some("Previous Frame"),
+        size: wgpu::Extent3d {
+            width,
+            height,
+            depth_or_array_layers: 1,
+        },
+        mip_level_count: 1,
+        sample_count: 1,
+        dimension: wgpu::TextureDimension::D2,
+        format,
+        usage: wgpu::TextureUsages::COPY_DST | wgpu::TextureUsages::TEXTURE_BINDING,
+        view_formats: &[],
+    });
+    let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+
+    let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+        label: Some("Feedback Bind Group"),
+        layout,
+        entries: &[
+            wgpu::BindGroupEntry {
+                binding: 0,
+                resource: wgpu::BindingResource::TextureView(&view),
+            },
+            wgpu::BindGroupEntry {
+                binding: 1,
+                resource: wgpu::BindingResource::Sampler(sampler),
+            },
+        ],
+    });
+
+    (texture, bind_group)
+}
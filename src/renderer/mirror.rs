@@ -0,0 +1,209 @@
+//! `--mirror`: the first output draws the shader and every other output shows that frame,
+//! stretched to fit, so there is one pipeline and one set of uniforms between them and they can't
+//! drift apart. The frame is always drawn offscreen for this, into the
+//! [`Upscaler`](super::upscale::Upscaler), and each [`Mirror`] draws it onto its own surface on
+//! the same device.
+//!
+//! A mirror only presents once the compositor has shown its last frame, so a covered output
+//! doesn't hold up the rest.
+
+use anyhow::{bail, Result};
+use log::debug;
+use sctk::{
+    output::OutputInfo,
+    shell::{wlr_layer::LayerSurface, WaylandSurface},
+};
+use wayland_client::{
+    protocol::{wl_callback::WlCallback, wl_surface::WlSurface},
+    Dispatch, Proxy, QueueHandle,
+};
+use wgpu::{Adapter, Device, Queue, RenderPipeline, Surface, SurfaceConfiguration, SurfaceError};
+
+use super::renderable::Renderable;
+
+// the wgpu surface goes before the wl_surface it draws to
+pub struct Mirror {
+    surface: Surface,
+    /// `None` until the surface has been configured for the current size.
+    surface_configuration: Option<SurfaceConfiguration>,
+    /// Draws the frame onto the surface. Built for the upscaler it was last drawn from.
+    pipeline: Option<RenderPipeline>,
+
+    layer: LayerSurface,
+    output_info: OutputInfo,
+    size: (u32, u32),
+    /// Whether a frame callback is outstanding.
+    frame_requested: bool,
+}
+
+impl Mirror {
+    pub fn new(output_info: OutputInfo, layer: LayerSurface, surface: Surface) -> Self {
+        Self {
+            surface,
+            surface_configuration: None,
+            pipeline: None,
+            layer,
+            output_info,
+            size: (0, 0),
+            frame_requested: false,
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        self.output_info.name.as_deref().unwrap_or("unnamed output")
+    }
+
+    pub fn wl_surface(&self) -> &WlSurface {
+        self.layer.wl_surface()
+    }
+
+    pub fn layer_matches(&self, layer: &LayerSurface) -> bool {
+        self.layer.wl_surface().id() == layer.wl_surface().id()
+    }
+
+    pub fn surface_matches(&self, surface: &WlSurface) -> bool {
+        self.layer.wl_surface().id() == surface.id()
+    }
+
+    /// Follows a new size from the compositor, configured before the next frame.
+    pub fn resize(&mut self, width: u32, height: u32) {
+        // zero means the compositor leaves the choice to us, keep what we have
+        if width == 0 || height == 0 || (width, height) == self.size {
+            return;
+        }
+
+        self.size = (width, height);
+        self.surface_configuration = None;
+    }
+
+    /// Starts over on `surface`, after the device has been rebuilt.
+    pub fn set_surface(&mut self, surface: Surface) {
+        self.surface = surface;
+        self.surface_configuration = None;
+        self.pipeline = None;
+        self.frame_requested = false;
+    }
+
+    /// Forgets the pipeline, for when the frame comes from a new upscaler.
+    pub fn reset_pipeline(&mut self) {
+        self.pipeline = None;
+    }
+
+    /// Called when the compositor shows a frame.
+    pub fn frame_done(&mut self) {
+        self.frame_requested = false;
+    }
+
+    /// Draws the frame `renderable` last drew onto the surface, set up like its surface where
+    /// this one supports it. Does nothing before the compositor has given a size, or while it
+    /// hasn't shown the last frame yet.
+    pub fn present<D>(
+        &mut self,
+        adapter: &Adapter,
+        device: &Device,
+        queue: &Queue,
+        renderable: &Renderable,
+        qh: &QueueHandle<D>,
+    ) -> Result<()>
+    where
+        D: Dispatch<WlCallback, WlSurface> + 'static,
+    {
+        let upscaler = match renderable.upscaler() {
+            Some(upscaler) => upscaler,
+            None => bail!("the frame isnt drawn offscreen, so it cant be mirrored"),
+        };
+        if self.size == (0, 0) || self.frame_requested {
+            return Ok(());
+        }
+
+        let like = renderable.surface_configuration();
+
+        let name = self.output_info.name.as_deref().unwrap_or("unnamed output");
+        let surface_configuration = self.surface_configuration.get_or_insert_with(|| {
+            configure(&self.surface, name, self.size, adapter, device, like)
+        });
+        let pipeline = self
+            .pipeline
+            .get_or_insert_with(|| upscaler.mirror_pipeline(device, surface_configuration.format));
+
+        let surface_texture = match self.surface.get_current_texture() {
+            Ok(surface_texture) => surface_texture,
+            // skipped like a frame of the output drawing the shader
+            Err(SurfaceError::Timeout) => return Ok(()),
+            Err(SurfaceError::Outdated | SurfaceError::Lost) => {
+                self.surface.configure(device, surface_configuration);
+                self.surface.get_current_texture()?
+            }
+            Err(e) => return Err(e.into()),
+        };
+        let view = surface_texture
+            .texture
+            .create_view(&wgpu::TextureViewDescriptor::default());
+
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("Mirror Encoder"),
+        });
+        upscaler.draw_mirror(
+            &mut encoder,
+            pipeline,
+            &view,
+            self.size,
+            renderable.clear_color(),
+        );
+        queue.submit(Some(encoder.finish()));
+
+        // goes out with the commit when the frame is presented
+        let wl_surface = self.layer.wl_surface();
+        wl_surface.frame(qh, wl_surface.clone());
+        self.frame_requested = true;
+        surface_texture.present();
+
+        Ok(())
+    }
+}
+
+/// Configures `surface` for `size` with `like`'s format, present mode and alpha mode, or whatever
+/// it prefers of those it doesn't support.
+fn configure(
+    surface: &Surface,
+    name: &str,
+    (width, height): (u32, u32),
+    adapter: &Adapter,
+    device: &Device,
+    like: &SurfaceConfiguration,
+) -> SurfaceConfiguration {
+    let capabilities = surface.get_capabilities(adapter);
+    let format = if capabilities.formats.contains(&like.format) {
+        like.format
+    } else {
+        capabilities.formats[0]
+    };
+    let present_mode = if capabilities.present_modes.contains(&like.present_mode) {
+        like.present_mode
+    } else {
+        wgpu::PresentMode::Fifo
+    };
+    let alpha_mode = if capabilities.alpha_modes.contains(&like.alpha_mode) {
+        like.alpha_mode
+    } else {
+        wgpu::CompositeAlphaMode::Auto
+    };
+
+    let surface_configuration = SurfaceConfiguration {
+        usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
+        format,
+        view_formats: vec![],
+        alpha_mode,
+        width,
+        height,
+        present_mode,
+    };
+
+    debug!(
+        "{}: configuring mirror {}x{} {:?} {:?} {:?}",
+        name, width, height, format, present_mode, alpha_mode
+    );
+    surface.configure(device, &surface_configuration);
+
+    surface_configuration
+}
@@ -1,126 +1,1219 @@
-use anyhow::{anyhow, Result};
+use std::{
+    ops::Range,
+    path::PathBuf,
+    sync::mpsc::{self, Receiver},
+    time::{Duration, Instant},
+};
+
+use anyhow::{anyhow, bail, Context, Result};
+use log::{debug, error, info, warn};
 use sctk::{
     output::OutputInfo,
+    seat::pointer::{AxisScroll, PointerEvent, PointerEventKind, BTN_LEFT},
     shell::{wlr_layer::LayerSurface, WaylandSurface},
 };
-use wayland_client::Proxy;
-use wgpu::{ShaderModule, ShaderModuleDescriptor};
+use wayland_client::{
+    protocol::{wl_callback::WlCallback, wl_surface::WlSurface},
+    Dispatch, Proxy, QueueHandle,
+};
+
+use super::{
+    adapter::{self, AdapterChoice},
+    feedback::Feedback,
+    mirror::Mirror,
+    pipe::{Capture, Pipe},
+    precompute::Precompute,
+    provider::{self, UniformProvider},
+    renderable::{self, Inputs, MouseEvent, Multisample, RenderConfig, RenderState, Renderable},
+    shader,
+    upscale::{self, Stretch, Upscaler},
+};
+use crate::config::{self, OutputSettings, Overlay, Placement, Transform};
+
+/// Runs `f`, returning any validation or out of memory error it causes on `device` rather than
+/// letting wgpu panic.
+pub fn catch_errors<T>(device: &wgpu::Device, f: impl FnOnce() -> T) -> Result<T> {
+    device.push_error_scope(wgpu::ErrorFilter::OutOfMemory);
+    device.push_error_scope(wgpu::ErrorFilter::Validation);
+    let value = f();
+    let validation = pollster::block_on(device.pop_error_scope());
+    let out_of_memory = pollster::block_on(device.pop_error_scope());
+
+    match validation.or(out_of_memory) {
+        Some(e) => Err(anyhow!("{}", e)),
+        None => Ok(value),
+    }
+}
+
+/// How the main pipeline is built, which the `--overlay`s drawn after it have to match.
+pub struct PipelineSetup<'a> {
+    /// See [`RenderConfig::new`].
+    pub coord_scale: f32,
+    pub inputs: Inputs,
+    /// Whether the shader encodes colors to sRGB itself, because the surface won't.
+    pub encode_srgb: bool,
+    pub bind_group_layouts: &'a [&'a wgpu::BindGroupLayout],
+    pub format: wgpu::TextureFormat,
+    pub sample_count: u32,
+}
+
+/// Compiles each of `overlays` into a pipeline drawn after the main one, with the same
+/// `custom_uniforms`.
+pub fn overlay_pipelines(
+    device: &wgpu::Device,
+    overlays: &[(Overlay, String)],
+    custom_uniforms: &[(String, f32)],
+    setup: &PipelineSetup,
+) -> Vec<Result<wgpu::RenderPipeline>> {
+    overlays
+        .iter()
+        .map(|(overlay, source)| {
+            catch_errors(device, || -> Result<_> {
+                let config = RenderConfig::new(
+                    device,
+                    source,
+                    custom_uniforms,
+                    // alpha is what lets what's below show through
+                    true,
+                    setup.coord_scale,
+                    setup.inputs,
+                    setup.encode_srgb,
+                )?;
+                Ok(renderable::create_pipeline(
+                    device,
+                    &config,
+                    setup.bind_group_layouts,
+                    setup.format,
+                    setup.sample_count,
+                    overlay.blend.into(),
+                ))
+            })
+            .and_then(|pipeline| pipeline)
+        })
+        .collect()
+}
+
+/// How long a frame callback can go unanswered before the surface is treated as covered.
+const OCCLUDED_AFTER: Duration = Duration::from_millis(500);
 
-use super::renderable::{RenderConfig, RenderState, Renderable};
+/// How many frames in a row can take longer than `--max-frame-ms` before the shader is replaced.
+const SLOW_FRAMES_ALLOWED: u32 = 3;
+
+/// How many times in a row [`OutputSurface::recover`] will try before giving up.
+const MAX_RECOVERIES: u32 = 3;
+
+/// Scroll distance from touchpads and other smooth sources counted as one wheel step, which is
+/// what compositors report for a click of the wheel.
+const SCROLL_STEP: f64 = 15.0;
+
+/// Wheel clicks as they are, anything else converted at [`SCROLL_STEP`].
+fn scroll_steps(scroll: AxisScroll) -> f32 {
+    if scroll.discrete != 0 {
+        scroll.discrete as f32
+    } else {
+        (scroll.absolute / SCROLL_STEP) as f32
+    }
+}
+
+/// Logs errors on `device` that weren't caught by an error scope, which would otherwise panic.
+fn log_uncaptured_errors(device: &wgpu::Device, name: String) {
+    device.on_uncaptured_error(Box::new(move |e| error!("{}: wgpu error: {}", name, e)));
+}
+
+/// A shader and what was loaded along with it. A new one is only swapped in once a pipeline has
+/// been built from it, so a reload that fails leaves the running shader and its uniforms as they
+/// were.
+struct Shaders {
+    source: String,
+    /// The `overlays` that could be read, with their source.
+    overlay_sources: Vec<(Overlay, String)>,
+    /// The `compute` shader's source, if it could be read and compiles.
+    compute_source: Option<String>,
+    /// `@param` annotations of `source` and the overlays.
+    params: Vec<shader::Param>,
+    /// `--uniform`s, `--provider` values and `params`, in the order the uniform buffer has them.
+    custom_uniforms: Vec<(String, f32)>,
+}
+
+impl Shaders {
+    /// Declares the `@param`s that aren't in `custom_uniforms` yet. Values already set are kept,
+    /// so a reload doesn't undo `set`.
+    fn new(
+        source: String,
+        overlay_sources: Vec<(Overlay, String)>,
+        compute_source: Option<String>,
+        custom_uniforms: &[(String, f32)],
+    ) -> Self {
+        let params: Vec<_> = std::iter::once(&source)
+            .chain(overlay_sources.iter().map(|(_, source)| source))
+            .flat_map(|source| shader::params(source))
+            .collect();
+        let custom_uniforms = shader::with_params(custom_uniforms, &params);
+
+        Shaders {
+            source,
+            overlay_sources,
+            compute_source,
+            params,
+            custom_uniforms,
+        }
+    }
+}
 
+// fields are dropped in order, so the pipeline and swapchain go before the device, and all of
+// the wgpu side goes before the wl_surface it draws to
 pub struct OutputSurface {
-    output_info: OutputInfo,
+    renderable: Option<Renderable>,
+    /// Other outputs showing what this one draws, with `--mirror`.
+    mirrors: Vec<Mirror>,
+
+    surface: wgpu::Surface,
+    queue: wgpu::Queue,
+    device: wgpu::Device,
+    adapter: wgpu::Adapter,
 
     layer: LayerSurface,
 
-    adapter: wgpu::Adapter,
-    device: wgpu::Device,
-    queue: wgpu::Queue,
-    surface: wgpu::Surface,
+    output_info: OutputInfo,
 
-    renderable: Option<Renderable>,
+    shader_path: Option<PathBuf>,
+    shaders: Shaders,
+    /// The bundled default with `defines` applied, what a shader that fails or is too slow is
+    /// replaced with.
+    default_source: String,
+    overlays: Vec<Overlay>,
+    present_mode: wgpu::PresentMode,
+    /// `None` with `--fps 0`, where frames are paced by frame callbacks alone.
+    frame_interval: Option<Duration>,
+    time_scale: f32,
+    render_scale: f32,
+    report_full_resolution: bool,
+    msaa: u32,
+    feedback: bool,
+    compute: Option<PathBuf>,
+    pass_format: Option<wgpu::TextureFormat>,
+    format: Option<wgpu::TextureFormat>,
+    transform: Transform,
+    /// `--aspect`, width over height.
+    aspect: Option<f32>,
+    upscale_filter: wgpu::FilterMode,
+    power: [f32; 2],
+    epoch: Option<Instant>,
+    placement: Placement,
+    seed: u32,
+    /// When the last frame was due, which the next one is timed from.
+    last_frame: Option<Instant>,
+    /// When the frame callback now outstanding was asked for.
+    frame_requested: Option<Instant>,
+    paused: bool,
+    once: bool,
+    only_on_change: bool,
+    frozen: bool,
+    /// `--provider`s and the custom uniforms their values go to.
+    providers: Vec<(Box<dyn UniformProvider>, Range<usize>)>,
+    defines: Vec<(String, String)>,
+    size: (u32, u32),
+    clear_color: wgpu::Color,
+    solid: bool,
+    adapter_choice: AdapterChoice,
+    pipe: Option<Pipe>,
+    max_frame_time: Option<Duration>,
+    /// Frames in a row that took longer than `max_frame_time`.
+    slow_frames: u32,
+    /// The frame being timed for `max_frame_time`: when it started, and when the gpu finished it
+    /// once it has.
+    frame_timing: Option<(Instant, Receiver<Instant>)>,
+    /// Times the gpu side has been rebuilt since the last frame that drew fine.
+    recoveries: u32,
 }
 
 impl OutputSurface {
+    /// Draws on `layer`, and with `--mirror` shows the same frame on each of `mirrors`. Fails if
+    /// they can't all be drawn to from one device.
     pub fn new(
         output_info: OutputInfo,
         layer: LayerSurface,
-        device: wgpu::Device,
-        surface: wgpu::Surface,
-        adapter: wgpu::Adapter,
-        queue: wgpu::Queue,
-    ) -> Self {
-        OutputSurface {
+        mirrors: Vec<(OutputInfo, LayerSurface)>,
+        settings: OutputSettings,
+    ) -> Result<Self> {
+        let name = output_info
+            .name
+            .clone()
+            .unwrap_or_else(|| "unnamed output".to_string());
+
+        let default_source = shader::load_fragment_shader(None, &settings.defines)?;
+        // a wallpaper is still better than none, `reload` tries the real shader again
+        let shader_source =
+            match shader::load_fragment_shader(settings.shader.as_deref(), &settings.defines) {
+                Ok(source) => source,
+                Err(e) => {
+                    error!("{}: {:#}", name, e);
+                    warn!("{}: falling back to the default shader", name);
+                    default_source.clone()
+                }
+            };
+        let mirror_wl_surfaces: Vec<_> = mirrors
+            .iter()
+            .map(|(_, layer)| layer.wl_surface())
+            .collect();
+        let (surface, mirror_surfaces, adapter, device, queue) = adapter::connect(
+            layer.wl_surface(),
+            &mirror_wl_surfaces,
+            &name,
+            &settings.adapter,
+        )?;
+        let mirrors = mirrors
+            .into_iter()
+            .zip(mirror_surfaces)
+            .map(|((output_info, layer), surface)| Mirror::new(output_info, layer, surface))
+            .collect();
+        if settings.dump_caps {
+            adapter::dump_caps(&name, &adapter, &surface);
+        }
+        // otherwise the clear color's alpha is silently ignored
+        let alpha_modes = surface.get_capabilities(&adapter).alpha_modes;
+        if settings.clear_color.a < 1.0
+            && !alpha_modes.contains(&wgpu::CompositeAlphaMode::PreMultiplied)
+        {
+            warn!(
+                "{}: transparency was asked for, but the driver only offers {:?} for this surface, \
+                 so the wallpaper will be opaque",
+                name, alpha_modes
+            );
+        }
+        let mut custom_uniforms = settings.uniforms;
+        let providers = settings
+            .providers
+            .iter()
+            .map(|&kind| {
+                let mut provider = provider::create(kind);
+                let uniforms = provider::uniforms(provider.as_mut());
+                let start = custom_uniforms.len();
+                custom_uniforms.extend(uniforms);
+                (provider, start..custom_uniforms.len())
+            })
+            .collect();
+        let pipe = settings.pipe.as_deref().map(|path| Pipe::open(path, &name));
+        log_uncaptured_errors(&device, name);
+
+        let mut output_surface = OutputSurface {
             output_info,
             layer,
             device,
             surface,
             adapter,
             queue,
+            shader_path: settings.shader,
+            shaders: Shaders::new(shader_source, Vec::new(), None, &custom_uniforms),
+            default_source,
+            overlays: settings.overlays,
+            present_mode: settings.present_mode,
+            frame_interval: settings.frame_interval,
+            time_scale: settings.time_scale,
+            render_scale: settings.render_scale,
+            report_full_resolution: settings.report_full_resolution,
+            msaa: settings.msaa,
+            feedback: settings.feedback,
+            compute: settings.compute,
+            pass_format: settings.pass_format,
+            format: settings.format,
+            transform: settings.transform,
+            aspect: settings.aspect,
+            upscale_filter: settings.upscale_filter,
+            power: [1.0, 1.0],
+            epoch: settings.epoch,
+            placement: settings.placement,
+            seed: settings.seed,
+            last_frame: None,
+            frame_requested: None,
+            paused: false,
+            once: settings.once,
+            only_on_change: settings.only_on_change,
+            frozen: false,
+            providers,
+            defines: settings.defines,
+            size: (0, 0),
+            clear_color: settings.clear_color,
+            solid: settings.solid,
+            adapter_choice: settings.adapter,
+            pipe,
+            max_frame_time: settings.max_frame_time,
+            slow_frames: 0,
+            frame_timing: None,
+            recoveries: 0,
             renderable: None,
+            mirrors,
+        };
+        // the compute shader is checked on the device, so only once there is one
+        output_surface.shaders = Shaders::new(
+            output_surface.shaders.source.clone(),
+            output_surface.load_overlays(),
+            output_surface.load_compute(),
+            &output_surface.shaders.custom_uniforms,
+        );
+
+        Ok(output_surface)
+    }
+
+    pub fn name(&self) -> &str {
+        self.output_info.name.as_deref().unwrap_or("unnamed output")
+    }
+
+    fn shader_name(&self) -> String {
+        match self.shader_path {
+            Some(ref path) => path.display().to_string(),
+            None => "the default shader".to_string(),
         }
     }
 
-    pub fn create_shader_module(&self, desc: ShaderModuleDescriptor) -> ShaderModule {
-        self.device.create_shader_module(desc)
+    /// Switches to the shader at `path` (or the bundled default), rebuilding the pipeline if one
+    /// is already running. If it can't be read or built the current one keeps running.
+    pub fn set_shader(&mut self, path: Option<PathBuf>) -> Result<()> {
+        let source = shader::load_fragment_shader(path.as_deref(), &self.defines)?;
+        let shaders = Shaders::new(
+            source,
+            self.load_overlays(),
+            self.load_compute(),
+            &self.shaders.custom_uniforms,
+        );
+
+        if self.renderable.is_some() {
+            let renderable = self.build(&shaders)?;
+            self.set_renderable(renderable);
+        }
+        self.shaders = shaders;
+        self.shader_path = path;
+
+        Ok(())
+    }
+
+    /// Re-reads the current shader from disk.
+    pub fn reload(&mut self) -> Result<()> {
+        self.set_shader(self.shader_path.clone())
+    }
+
+    pub fn set_paused(&mut self, paused: bool) {
+        self.paused = paused;
+        if let Some(ref mut r) = self.renderable {
+            r.set_paused(paused);
+        }
+    }
+
+    /// Whether the wallpaper should let whatever is below the layer show through.
+    pub fn transparent(&self) -> bool {
+        self.clear_color.a < 1.0
+    }
+
+    /// How much smaller `resolution` is than the surface.
+    fn resolution_scale(&self) -> f32 {
+        if self.report_full_resolution {
+            1.0
+        } else {
+            self.render_scale
+        }
+    }
+
+    /// Copies the latest `--provider` values into their uniforms.
+    fn poll_providers(&mut self) {
+        for (provider, uniforms) in self.providers.iter_mut() {
+            for (index, value) in uniforms.clone().zip(provider.value()) {
+                if let Some(uniform) = self.shaders.custom_uniforms.get_mut(index) {
+                    uniform.1 = value;
+                }
+                if let Some(ref mut r) = self.renderable {
+                    r.set_custom_uniform(index, value);
+                }
+            }
+        }
+    }
+
+    /// Reads the `--overlay` shaders. One that can't be read is left out.
+    fn load_overlays(&self) -> Vec<(Overlay, String)> {
+        self.overlays
+            .iter()
+            .filter_map(|overlay| {
+                match shader::load_fragment_shader(Some(&overlay.shader), &self.defines) {
+                    Ok(source) => Some((overlay.clone(), source)),
+                    Err(e) => {
+                        error!("{}: {:#}", self.name(), e);
+                        None
+                    }
+                }
+            })
+            .collect()
+    }
+
+    /// Reads the `--compute` shader, checking that it compiles. One that doesn't is left out, so
+    /// the shader can't read `precomputed`.
+    fn load_compute(&self) -> Option<String> {
+        let source = self.compute.as_ref().map(|path| {
+            let source = shader::load_compute_shader(path, &self.defines)?;
+            catch_errors(&self.device, || {
+                Precompute::new(&self.device, &source, (1, 1))
+            })
+            .with_context(|| format!("couldnt compile {}", path.display()))?;
+            Ok::<_, anyhow::Error>(source)
+        });
+        match source {
+            Some(Ok(source)) => Some(source),
+            Some(Err(e)) => {
+                error!("{}: {:#}", self.name(), e);
+                None
+            }
+            None => None,
+        }
     }
 
-    fn logical_size(&self) -> Result<(u32, u32)> {
-        let (width, height) = self.output_info.logical_size.ok_or(anyhow!("illogical"))?;
-        Ok((width.unsigned_abs(), height.unsigned_abs()))
+    /// Textures `shaders` read besides the uniforms.
+    fn inputs(&self, shaders: &Shaders) -> Inputs {
+        Inputs {
+            feedback: self.feedback,
+            precomputed: shaders.compute_source.is_some(),
+        }
+    }
+
+    /// Updates one of the `--uniform` or `@param` values, keeping a `@param` within its range.
+    /// Only names given at startup or by a loaded shader exist in the shader.
+    pub fn set_uniform(&mut self, name: &str, value: f32) -> Result<()> {
+        let index = self
+            .shaders
+            .custom_uniforms
+            .iter()
+            .position(|(n, _)| n == name)
+            .ok_or_else(|| anyhow!("no uniform called {}", name))?;
+        let value = match self.shaders.params.iter().find(|param| param.name == name) {
+            Some(param) => value.clamp(param.min, param.max),
+            None => value,
+        };
+
+        self.shaders.custom_uniforms[index].1 = value;
+        if let Some(ref mut r) = self.renderable {
+            r.set_custom_uniform(index, value);
+        }
+        self.changed();
+
+        Ok(())
+    }
+
+    pub fn set_fps(&mut self, fps: u32) {
+        self.frame_interval = config::frame_interval(fps);
+    }
+
+    /// Jumps the shader's clock to `secs`.
+    pub fn seek(&mut self, secs: f32) {
+        if let Some(ref mut r) = self.renderable {
+            r.seek(secs);
+        }
+        self.changed();
+    }
+
+    /// Updates the `power` uniform, see `--power-uniform`.
+    pub fn set_power(&mut self, power: [f32; 2]) {
+        if power == self.power {
+            return;
+        }
+        self.power = power;
+        if let Some(ref mut r) = self.renderable {
+            r.set_power(power);
+        }
+        self.changed();
+    }
+
+    /// Something the shader reads has changed, which with `--only-on-change` calls for a frame.
+    fn changed(&mut self) {
+        if self.only_on_change {
+            self.frozen = false;
+        }
+    }
+
+    /// How long until this surface wants to draw its next frame, or `None` if it won't until
+    /// something changes.
+    pub fn next_frame_in(&self) -> Option<Duration> {
+        if self.paused || self.frozen || self.occluded() {
+            return None;
+        }
+
+        match (self.frame_interval, self.last_frame) {
+            // woken by the frame callback instead
+            (None, _) if self.frame_requested.is_some() => None,
+            (None, _) => Some(Duration::ZERO),
+            (Some(frame_interval), None) => Some(frame_interval),
+            (Some(frame_interval), Some(last_frame)) => {
+                Some(frame_interval.saturating_sub(last_frame.elapsed()))
+            }
+        }
+    }
+
+    /// Compiles `shaders` for this surface.
+    fn render_config(&self, shaders: &Shaders) -> Result<RenderConfig> {
+        catch_errors(&self.device, || {
+            RenderConfig::new(
+                &self.device,
+                &shaders.source,
+                &shaders.custom_uniforms,
+                self.transparent(),
+                self.resolution_scale() / self.render_scale,
+                self.inputs(shaders),
+                !self.swapchain_format().describe().srgb,
+            )
+        })
+        .and_then(|config| config)
+        .with_context(|| format!("{}: couldnt compile {}", self.name(), self.shader_name()))
     }
 
     pub fn layer_matches(&self, layer: &LayerSurface) -> bool {
         self.layer.wl_surface().id() == layer.wl_surface().id()
     }
 
-    pub fn render(&mut self) -> Result<()> {
-        match self.renderable {
+    pub fn surface_matches(&self, surface: &WlSurface) -> bool {
+        self.layer.wl_surface().id() == surface.id()
+    }
+
+    /// Follows a new size from the compositor for one of the mirrors. Returns whether `layer` is
+    /// one of them.
+    pub fn resize_mirror(&mut self, layer: &LayerSurface, width: u32, height: u32) -> bool {
+        let mirror = match self.mirrors.iter_mut().find(|m| m.layer_matches(layer)) {
+            Some(mirror) => mirror,
+            None => return false,
+        };
+        mirror.resize(width, height);
+        // a mirror only gets a frame when one is drawn
+        self.frozen = false;
+        true
+    }
+
+    /// Called when the compositor shows a frame on one of the mirrors.
+    pub fn mirror_frame_done(&mut self, surface: &WlSurface) {
+        for mirror in self.mirrors.iter_mut() {
+            if mirror.surface_matches(surface) {
+                mirror.frame_done();
+            }
+        }
+    }
+
+    /// Stops mirroring onto `layer`, if it's one of the mirrors.
+    pub fn close_mirror(&mut self, layer: &LayerSurface) {
+        self.mirrors.retain(|mirror| {
+            let closed = mirror.layer_matches(layer);
+            if closed {
+                info!("{}: layer closed by the compositor", mirror.name());
+            }
+            !closed
+        });
+    }
+
+    /// Feeds a pointer event on this surface through to the shader's mouse uniforms. Only the
+    /// left button counts as a click.
+    pub fn set_mouse(&mut self, event: &PointerEvent) {
+        // wayland puts the origin at the top left, frag_coord has it at the bottom left
+        let (x, y) = event.position;
+        let (width, height) = (self.size.0 as f32, self.size.1 as f32);
+        let [x, y] = self
+            .transform
+            .surface_to_picture([x as f32 / width, (height - y as f32) / height]);
+        // with --aspect the picture only covers the middle of the surface
+        let (width, height) = self.transform.picture_size(self.size);
+        let (fit_width, fit_height) = upscale::fit_aspect((width, height), self.aspect);
+        let x = x * width as f32 - (width - fit_width) as f32 / 2.0;
+        let y = y * height as f32 - (height - fit_height) as f32 / 2.0;
+        let scale = self.resolution_scale();
+        let position = [x * scale, y * scale];
+
+        let mouse_event = match event.kind {
+            PointerEventKind::Motion { .. } => MouseEvent::Motion(position),
+            PointerEventKind::Press {
+                button: BTN_LEFT, ..
+            } => MouseEvent::Press(position),
+            PointerEventKind::Release {
+                button: BTN_LEFT, ..
+            } => MouseEvent::Release(position),
+            PointerEventKind::Axis {
+                horizontal,
+                vertical,
+                ..
+            } => MouseEvent::Scroll([scroll_steps(horizontal), -scroll_steps(vertical)]),
+            _ => return,
+        };
+        if let Some(ref mut r) = self.renderable {
+            r.mouse(mouse_event);
+            self.changed();
+        }
+    }
+
+    /// Whether the compositor has held on to a frame callback long enough that the surface is
+    /// probably covered. Drawing stops until the callback arrives.
+    fn occluded(&self) -> bool {
+        self.frame_requested
+            .is_some_and(|requested| requested.elapsed() >= OCCLUDED_AFTER)
+    }
+
+    /// Called when the compositor shows a frame, meaning the surface is visible.
+    pub fn frame_done(&mut self) {
+        self.frame_requested = None;
+    }
+
+    /// Compiles the `--overlay` shaders into pipelines drawn after the main one. One that doesn't
+    /// compile is left out with a warning.
+    fn overlay_pipelines(
+        &self,
+        shaders: &Shaders,
+        bind_group_layouts: &[&wgpu::BindGroupLayout],
+        format: wgpu::TextureFormat,
+        sample_count: u32,
+    ) -> Vec<wgpu::RenderPipeline> {
+        let setup = PipelineSetup {
+            coord_scale: self.resolution_scale() / self.render_scale,
+            inputs: self.inputs(shaders),
+            encode_srgb: !self.swapchain_format().describe().srgb,
+            bind_group_layouts,
+            format,
+            sample_count,
+        };
+        let built = overlay_pipelines(
+            &self.device,
+            &shaders.overlay_sources,
+            &shaders.custom_uniforms,
+            &setup,
+        );
+
+        let mut pipelines = Vec::new();
+        for ((overlay, _), pipeline) in shaders.overlay_sources.iter().zip(built) {
+            match pipeline {
+                Ok(pipeline) => pipelines.push(pipeline),
+                Err(e) => warn!(
+                    "{}: leaving out overlay {}: {:#}",
+                    self.name(),
+                    overlay.shader.display(),
+                    e
+                ),
+            }
+        }
+
+        pipelines
+    }
+
+    /// Builds the pipeline for the current shader. If it doesn't compile, the error is logged and
+    /// the bundled default is used instead, so the output isn't left black.
+    pub fn prepare(&mut self) -> Result<()> {
+        let e = match self.build(&self.shaders) {
+            Ok(renderable) => {
+                self.set_renderable(renderable);
+                return Ok(());
+            }
+            Err(e) if self.shader_path.is_some() => e,
+            Err(e) => return Err(e),
+        };
+
+        error!("{:#}", e);
+        self.use_default_shader()
+    }
+
+    /// Swaps the current shader for the bundled default, keeping its path for `reload`.
+    fn use_default_shader(&mut self) -> Result<()> {
+        warn!("{}: falling back to the default shader", self.name());
+        let shaders = Shaders::new(
+            self.default_source.clone(),
+            self.shaders.overlay_sources.clone(),
+            self.shaders.compute_source.clone(),
+            &self.shaders.custom_uniforms,
+        );
+        let renderable = self.build(&shaders)?;
+        self.set_renderable(renderable);
+        self.shaders = shaders;
+
+        Ok(())
+    }
+
+    pub fn output_matches(&self, info: &OutputInfo) -> bool {
+        self.output_info.id == info.id
+    }
+
+    /// Takes on changes to the output, such as a new mode.
+    pub fn update_output(&mut self, output_info: OutputInfo) {
+        self.output_info = output_info;
+        let refresh_rate = self.refresh_rate();
+        if let Some(ref mut r) = self.renderable {
+            r.set_refresh_rate(refresh_rate);
+        }
+        self.changed();
+    }
+
+    /// Of the output's current mode in Hz, or 0 if it doesn't have one.
+    fn refresh_rate(&self) -> f32 {
+        self.output_info
+            .modes
+            .iter()
+            .find(|mode| mode.current)
+            .map_or(0.0, |mode| mode.refresh_rate as f32 / 1000.0)
+    }
+
+    /// Whether the compositor has given the surface a size yet.
+    pub fn has_size(&self) -> bool {
+        self.size != (0, 0)
+    }
+
+    pub fn has_pipeline(&self) -> bool {
+        self.renderable.is_some()
+    }
+
+    /// Follows a new size from the compositor, reconfiguring the surface if a pipeline is running.
+    pub fn resize(&mut self, width: u32, height: u32) {
+        // zero means the compositor leaves the choice to us, keep what we have
+        if width == 0 || height == 0 || (width, height) == self.size {
+            return;
+        }
+
+        self.size = (width, height);
+        self.frozen = false;
+        if let Some(ref mut r) = self.renderable {
+            r.resize(&self.surface, &self.device, width, height);
+        }
+    }
+
+    /// Draws a frame if one is due. Returns whether a frame was presented.
+    pub fn render<D>(&mut self, qh: &QueueHandle<D>) -> Result<bool>
+    where
+        D: Dispatch<WlCallback, WlSurface> + 'static,
+    {
+        if self.paused || self.frozen || self.occluded() {
+            return Ok(false);
+        }
+
+        let due = match (self.frame_interval, self.last_frame) {
+            (None, _) => self.frame_requested.is_none(),
+            (Some(_), None) => true,
+            (Some(frame_interval), Some(last_frame)) => last_frame.elapsed() >= frame_interval,
+        };
+        if !due {
+            return Ok(false);
+        }
+
+        self.check_frame_time();
+        self.poll_providers();
+        let presented = match self.renderable {
             Some(ref mut r) => {
-                r.frame_start(&mut self.surface)?;
-                r.render(&mut self.device, &mut self.queue)?;
-                r.frame_finish()
+                // timed from when the frame was due rather than when it was drawn, so waking up
+                // late or slow frames don't pull the frame rate below --fps. once a whole frame
+                // behind, the schedule starts over instead of rushing out frames to catch up
+                let now = Instant::now();
+                self.last_frame = Some(match (self.frame_interval, self.last_frame) {
+                    (Some(frame_interval), Some(last_frame))
+                        if now < last_frame + frame_interval * 2 =>
+                    {
+                        last_frame + frame_interval
+                    }
+                    _ => now,
+                });
+                if !r.frame_start(&mut self.surface, &self.device)? {
+                    return Ok(false);
+                }
+                let started = Instant::now();
+                catch_errors(&self.device, || r.render(&self.device, &self.queue))??;
+
+                // goes out with the commit when the frame is presented, and only comes back once
+                // the compositor has shown it. asked for only once there is a frame to present,
+                // a skipped or failed frame commits nothing and the callback would never come
+                let requested = self.frame_requested.is_none();
+                if requested {
+                    let wl_surface = self.layer.wl_surface();
+                    wl_surface.frame(qh, wl_surface.clone());
+                    self.frame_requested = Some(Instant::now());
+                }
+                if let Err(e) = r.frame_finish() {
+                    if requested {
+                        self.frame_requested = None;
+                    }
+                    return Err(e);
+                }
+                self.recoveries = 0;
+
+                for mirror in self.mirrors.iter_mut() {
+                    if let Err(e) = mirror.present(&self.adapter, &self.device, &self.queue, r, qh)
+                    {
+                        warn!("{}: {:#}", mirror.name(), e);
+                    }
+                }
+
+                // without timestamp queries the gpu can only be timed by when it says it's done.
+                // waiting for that here would hold up the other outputs, so it's picked up
+                // before the next frame instead, and frames drawn meanwhile aren't timed
+                if self.max_frame_time.is_some() && self.frame_timing.is_none() {
+                    let (sender, receiver) = mpsc::channel();
+                    self.queue.on_submitted_work_done(move || {
+                        let _ = sender.send(Instant::now());
+                    });
+                    self.frame_timing = Some((started, receiver));
+                }
+
+                // with --once the first frame is the only one, it stays up until the surface is
+                // resized or the shader changes, and is then redrawn at the same time
+                if self.once {
+                    self.frozen = true;
+                    r.set_paused(true);
+                } else if self.only_on_change {
+                    // the clock keeps going, the next change just shows wherever it's got to
+                    self.frozen = true;
+                }
+
+                true
             }
-            None => Ok(()),
+            None => false,
+        };
+
+        if self.slow_frames >= SLOW_FRAMES_ALLOWED {
+            self.slow_frames = 0;
+            // nothing lighter to fall back to
+            if self.shaders.source == self.default_source {
+                return Ok(presented);
+            }
+            warn!(
+                "{}: {} took over {:?} a frame {} times in a row",
+                self.name(),
+                self.shader_name(),
+                self.max_frame_time.unwrap_or_default(),
+                SLOW_FRAMES_ALLOWED
+            );
+            self.use_default_shader()?;
         }
+
+        Ok(presented)
     }
 
-    pub fn prep_render_pipeline(&mut self, config: &RenderConfig) -> Result<()> {
-        let swapchain_capabilities = self.surface.get_capabilities(&self.adapter);
-        let swapchain_format = swapchain_capabilities.formats[0];
+    /// Counts the frame being timed towards `slow_frames` once the gpu has finished it, or as soon
+    /// as it has taken longer than `max_frame_time` either way.
+    fn check_frame_time(&mut self) {
+        let (max_frame_time, (started, finished)) = match (self.max_frame_time, &self.frame_timing)
+        {
+            (Some(max_frame_time), Some(frame_timing)) => (max_frame_time, frame_timing),
+            _ => return,
+        };
 
-        let frag_state = wgpu::FragmentState {
-            module: &config.frag_shader,
-            entry_point: "main",
-            targets: &[Some(swapchain_format.into())],
+        // runs the callback if the frame is done, without waiting for it
+        self.device.poll(wgpu::Maintain::Poll);
+        let took = match finished.try_recv() {
+            Ok(finished) => finished.duration_since(*started),
+            Err(_) if started.elapsed() > max_frame_time => started.elapsed(),
+            Err(_) => return,
         };
+        self.frame_timing = None;
+
+        if took > max_frame_time {
+            self.slow_frames += 1;
+        } else {
+            self.slow_frames = 0;
+        }
+    }
+
+    /// Rebuilds the surface, device and pipeline on the same layer after a frame failed, which is
+    /// how a lost device shows up. Gives up once it has failed [`MAX_RECOVERIES`] times in a row,
+    /// leaving the output on its last frame.
+    pub fn recover(&mut self) -> Result<()> {
+        // dropping the old pipeline first, so a device that has gone away isn't waited on
+        self.renderable = None;
+        if self.recoveries >= MAX_RECOVERIES {
+            bail!("giving up after {} attempts to recover", MAX_RECOVERIES);
+        }
+        self.recoveries += 1;
+        warn!(
+            "{}: rebuilding the gpu side, attempt {} of {}",
+            self.name(),
+            self.recoveries,
+            MAX_RECOVERIES
+        );
+
+        let mirror_wl_surfaces: Vec<_> = self.mirrors.iter().map(|m| m.wl_surface()).collect();
+        let (surface, mirror_surfaces, adapter, device, queue) = adapter::connect(
+            self.layer.wl_surface(),
+            &mirror_wl_surfaces,
+            self.name(),
+            &self.adapter_choice,
+        )?;
+        log_uncaptured_errors(&device, self.name().to_string());
+        for (mirror, surface) in self.mirrors.iter_mut().zip(mirror_surfaces) {
+            mirror.set_surface(surface);
+        }
+        self.surface = surface;
+        self.queue = queue;
+        self.device = device;
+        self.adapter = adapter;
+        // the old queue will never say the frame it was timing is done, and whatever was
+        // presented last may never be shown
+        self.frame_timing = None;
+        self.frame_requested = None;
+
+        let renderable = self.build(&self.shaders)?;
+        self.set_renderable(renderable);
+
+        Ok(())
+    }
+
+    /// Seconds between the last two frames drawn.
+    pub fn frame_time(&self) -> Option<f32> {
+        self.renderable.as_ref().map(|r| r.frame_time())
+    }
 
-        let vert_state = wgpu::VertexState {
-            module: &config.vert_shader,
-            entry_point: "main",
-            buffers: &[],
+    /// The `--format` if the surface supports it, otherwise the first sRGB format it does, like
+    /// Shadertoy's output. Falls back to whatever the surface prefers.
+    fn swapchain_format(&self) -> wgpu::TextureFormat {
+        let formats = self.surface.get_capabilities(&self.adapter).formats;
+        self.format
+            .filter(|format| formats.contains(format))
+            .or_else(|| {
+                formats
+                    .iter()
+                    .copied()
+                    .find(|format| format.describe().srgb)
+            })
+            .unwrap_or(formats[0])
+    }
+
+    /// The `--pass-format` if the adapter can draw to and sample from it, otherwise the surface's
+    /// `swapchain_format`.
+    fn render_format(&self, swapchain_format: wgpu::TextureFormat) -> wgpu::TextureFormat {
+        let format = match self.pass_format {
+            Some(format) => format,
+            None => return swapchain_format,
         };
 
-        let render_state = RenderState::new(&self.device, &self.output_info);
+        let features = self.adapter.get_texture_format_features(format);
+        let usages = wgpu::TextureUsages::RENDER_ATTACHMENT
+            | wgpu::TextureUsages::TEXTURE_BINDING
+            | wgpu::TextureUsages::COPY_SRC;
+        if features.allowed_usages.contains(usages)
+            && features
+                .flags
+                .contains(wgpu::TextureFormatFeatureFlags::FILTERABLE)
+        {
+            format
+        } else {
+            warn!(
+                "{}: cant render to {:?}, falling back to {:?}",
+                self.name(),
+                format,
+                swapchain_format
+            );
+            swapchain_format
+        }
+    }
 
-        let pipeline_layout = self
+    /// The `--msaa` sample count if `format` supports it, otherwise 1.
+    fn sample_count(&self, format: wgpu::TextureFormat) -> u32 {
+        // 4 is always there, anything else depends on the adapter
+        let supported = if self
             .device
-            .create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
-                label: None,
-                bind_group_layouts: &[&render_state.uniform_bind_group_layout],
-                push_constant_ranges: &[],
-            });
+            .features()
+            .contains(wgpu::Features::TEXTURE_ADAPTER_SPECIFIC_FORMAT_FEATURES)
+        {
+            self.adapter
+                .get_texture_format_features(format)
+                .flags
+                .sample_count_supported(self.msaa)
+        } else {
+            self.msaa == 1 || self.msaa == 4
+        };
 
-        let pipeline = self
-            .device
-            .create_render_pipeline(&wgpu::RenderPipelineDescriptor {
-                label: None,
-                layout: Some(&pipeline_layout),
-                vertex: vert_state,
-                fragment: Some(frag_state),
-                primitive: wgpu::PrimitiveState::default(),
-                depth_stencil: None,
-                multisample: wgpu::MultisampleState::default(),
-                multiview: None,
-            });
-
-        let (width, height) = self.logical_size()?;
+        if supported {
+            self.msaa
+        } else {
+            warn!(
+                "{}: {}x msaa not supported for {:?}, falling back to none",
+                self.name(),
+                self.msaa,
+                format
+            );
+            1
+        }
+    }
+
+    /// Compiles `shaders` and builds everything needed to draw them, leaving the running pipeline
+    /// alone until it's handed to [`Self::set_renderable`].
+    fn build(&self, shaders: &Shaders) -> Result<Renderable> {
+        let config = self.render_config(shaders)?;
+        self.prep_render_pipeline(shaders, &config)
+    }
+
+    /// Starts drawing with `renderable` in place of the current pipeline.
+    fn set_renderable(&mut self, renderable: Renderable) {
+        self.frozen = false;
+        self.renderable = Some(renderable);
+        for mirror in self.mirrors.iter_mut() {
+            mirror.reset_pipeline();
+        }
+    }
+
+    fn prep_render_pipeline(&self, shaders: &Shaders, config: &RenderConfig) -> Result<Renderable> {
+        let swapchain_capabilities = self.surface.get_capabilities(&self.adapter);
+        let swapchain_format = self.swapchain_format();
+        match self.format {
+            Some(format) if format != swapchain_format => warn!(
+                "{}: format {:?} not supported, using {:?}",
+                self.name(),
+                format,
+                swapchain_format
+            ),
+            _ if !swapchain_format.describe().srgb => info!(
+                "{}: no sRGB format available, encoding colors in the shader",
+                self.name()
+            ),
+            _ => {}
+        }
+
+        let present_mode = if swapchain_capabilities
+            .present_modes
+            .contains(&self.present_mode)
+        {
+            self.present_mode
+        } else {
+            warn!(
+                "{}: present mode {:?} not supported, falling back to fifo",
+                self.name(),
+                self.present_mode
+            );
+            wgpu::PresentMode::Fifo
+        };
+
+        let alpha_mode = if self.transparent()
+            && swapchain_capabilities
+                .alpha_modes
+                .contains(&wgpu::CompositeAlphaMode::PreMultiplied)
+        {
+            wgpu::CompositeAlphaMode::PreMultiplied
+        } else {
+            wgpu::CompositeAlphaMode::Auto
+        };
+
+        let render_format = self.render_format(swapchain_format);
+
+        // feedback, --pipe and --mirror need the frame drawn somewhere it can be copied from,
+        // which the upscaler is
+        let offscreen = self.render_scale != 1.0
+            || self.feedback
+            || self.pipe.is_some()
+            || !self.mirrors.is_empty()
+            || self.transform != Transform::Normal
+            || self.aspect.is_some()
+            || render_format != swapchain_format;
+        let upscaler = offscreen.then(|| {
+            Upscaler::new(
+                &self.device,
+                render_format,
+                swapchain_format,
+                self.size,
+                Stretch {
+                    scale: self.render_scale,
+                    full_resolution: self.report_full_resolution,
+                    transform: self.transform,
+                    filter: self.upscale_filter,
+                    aspect: self.aspect,
+                },
+            )
+        });
+        let (render_size, resolution) = match upscaler {
+            Some(ref upscaler) => (
+                upscaler.render_size(self.size),
+                upscaler.resolution(self.size),
+            ),
+            None => (self.size, self.size),
+        };
+
+        let sample_count = self.sample_count(render_format);
+        let multisample = (sample_count > 1)
+            .then(|| Multisample::new(&self.device, render_format, render_size, sample_count));
+        let feedback = self
+            .feedback
+            .then(|| Feedback::new(&self.device, render_format, render_size));
+        // checked when it was loaded
+        let precompute = shaders
+            .compute_source
+            .as_ref()
+            .map(|source| Precompute::new(&self.device, source, render_size));
+
+        let render_state = RenderState::new(
+            &self.device,
+            resolution,
+            &shaders.custom_uniforms,
+            self.time_scale,
+            self.epoch,
+            self.placement.scaled(self.resolution_scale()),
+        );
+        let mut bind_group_layouts = vec![&render_state.uniform_bind_group_layout];
+        bind_group_layouts.extend(feedback.as_ref().map(|f| f.bind_group_layout()));
+        bind_group_layouts.extend(precompute.as_ref().map(|p| p.bind_group_layout()));
+        let pipeline = catch_errors(&self.device, || {
+            renderable::create_pipeline(
+                &self.device,
+                config,
+                &bind_group_layouts,
+                render_format,
+                sample_count,
+                // draw over the clear color rather than replacing it
+                wgpu::BlendState::ALPHA_BLENDING,
+            )
+        })
+        .with_context(|| {
+            format!(
+                "{}: couldnt create a pipeline for {}",
+                self.name(),
+                self.shader_name()
+            )
+        })?;
+        let overlays =
+            self.overlay_pipelines(shaders, &bind_group_layouts, render_format, sample_count);
+
+        let (width, height) = self.size;
         let surface_config = wgpu::SurfaceConfiguration {
             usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
             format: swapchain_format,
             view_formats: vec![],
             //view_formats: vec![cap.formats[0]],
-            alpha_mode: wgpu::CompositeAlphaMode::Auto,
+            alpha_mode,
             width,
             height,
-            // Wayland is inherently a mailbox system.
-            present_mode: wgpu::PresentMode::Mailbox,
+            present_mode,
         };
 
+        debug!(
+            "{}: configuring {}x{} {:?} {:?} {:?}",
+            self.name(),
+            width,
+            height,
+            swapchain_format,
+            present_mode,
+            alpha_mode
+        );
         self.surface.configure(&self.device, &surface_config);
 
-        self.renderable = Some(Renderable::new(pipeline, surface_config, render_state)?);
+        let mut renderable = Renderable::new(
+            pipeline,
+            surface_config,
+            render_state,
+            self.clear_color,
+            upscaler,
+            multisample,
+            feedback,
+        )?;
+        if let Some(ref pipe) = self.pipe {
+            match Capture::new(&self.device, render_format, render_size, pipe) {
+                Ok(capture) => renderable.set_capture(Some(capture)),
+                Err(e) => warn!("{}: {:#}", self.name(), e),
+            }
+        }
+        renderable.set_precompute(precompute);
+        renderable.set_overlays(overlays);
+        renderable.set_solid(self.solid);
+        renderable.set_seed(self.seed);
+        renderable.set_power(self.power);
+        renderable.set_refresh_rate(self.refresh_rate());
+        renderable.set_paused(self.paused);
 
-        Ok(())
+        Ok(renderable)
+    }
+}
+
+impl Drop for OutputSurface {
+    fn drop(&mut self) {
+        // don't pull resources out from under work the gpu is still doing
+        self.renderable = None;
+        self.device.poll(wgpu::Maintain::Wait);
     }
 }
@@ -0,0 +1,208 @@
+//! `--pipe`: every frame drawn is read back from the gpu and written to a file or FIFO as raw
+//! RGBA, 8 bits per channel, rows from the top down with no padding. Frames are the size the
+//! shader draws at, the surface size times `--render-scale`, so for a 2560x1440 output something
+//! like this will pick them up:
+//!
+//! ```text
+//! mkfifo /tmp/glpaper
+//! ffmpeg -f rawvideo -pixel_format rgba -video_size 2560x1440 -framerate 30 -i /tmp/glpaper out.mkv
+//! ```
+//!
+//! Writing happens on its own thread so a slow reader can't hold up the wallpaper. Frames it
+//! isn't ready for are dropped. Reading back doesn't wait on the gpu either: a frame goes out
+//! when the next one is drawn, if the gpu has finished copying it by then, and is dropped if not.
+//! So the last frame before drawing stops, like the only one with `--once`, isn't sent.
+
+use std::{
+    fs::File,
+    io::Write,
+    path::Path,
+    sync::mpsc::{self, Receiver, SyncSender},
+    thread,
+};
+
+use anyhow::{bail, Result};
+use log::{info, warn};
+use wgpu::{Buffer, BufferAsyncError, CommandEncoder, Device, Texture, TextureFormat};
+
+/// Frames waiting to be written before new ones are dropped.
+const QUEUED_FRAMES: usize = 2;
+
+/// The writer end of `--pipe`. Stays open across pipeline rebuilds so readers see one stream.
+pub struct Pipe {
+    sender: SyncSender<Vec<u8>>,
+}
+
+impl Pipe {
+    /// Starts writing to `path`. A FIFO isn't opened until something reads from it, so that
+    /// happens on the writer thread too.
+    pub fn open(path: &Path, name: &str) -> Self {
+        let (sender, receiver) = mpsc::sync_channel::<Vec<u8>>(QUEUED_FRAMES);
+        let path = path.to_path_buf();
+        let name = name.to_string();
+
+        thread::spawn(move || {
+            let mut file = match File::create(&path) {
+                Ok(file) => file,
+                Err(e) => {
+                    warn!("{}: couldnt open {}: {}", name, path.display(), e);
+                    return;
+                }
+            };
+            info!("{}: writing frames to {}", name, path.display());
+
+            for frame in receiver {
+                if let Err(e) = file.write_all(&frame) {
+                    warn!("{}: stopped writing to {}: {}", name, path.display(), e);
+                    return;
+                }
+            }
+        });
+
+        Self { sender }
+    }
+}
+
+/// Reads frames back into a buffer and hands them to a [`Pipe`].
+pub struct Capture {
+    sender: SyncSender<Vec<u8>>,
+    /// Whether red and blue have to be swapped to get RGBA.
+    bgra: bool,
+
+    /// One is copied into while the other is read back.
+    buffers: [Buffer; 2],
+    /// The buffer the next frame is copied into.
+    current: usize,
+    /// The buffer being read back, and where the result of mapping it turns up.
+    mapping: Option<(usize, Receiver<Result<(), BufferAsyncError>>)>,
+    size: (u32, u32),
+    padded_bytes_per_row: u32,
+}
+
+impl Capture {
+    /// Fails for formats that aren't 8 bit RGBA or BGRA.
+    pub fn new(
+        device: &Device,
+        format: TextureFormat,
+        size: (u32, u32),
+        pipe: &Pipe,
+    ) -> Result<Self> {
+        let bgra = match format {
+            TextureFormat::Rgba8Unorm | TextureFormat::Rgba8UnormSrgb => false,
+            TextureFormat::Bgra8Unorm | TextureFormat::Bgra8UnormSrgb => true,
+            format => bail!("cant pipe {:?} frames, only 8 bit RGBA or BGRA", format),
+        };
+        let (buffers, padded_bytes_per_row) = create_buffers(device, size);
+
+        Ok(Self {
+            sender: pipe.sender.clone(),
+            bgra,
+            buffers,
+            current: 0,
+            mapping: None,
+            size,
+            padded_bytes_per_row,
+        })
+    }
+
+    /// Starts over at the new size. The frame being read back is dropped.
+    pub fn resize(&mut self, device: &Device, size: (u32, u32)) {
+        let (buffers, padded_bytes_per_row) = create_buffers(device, size);
+        self.buffers = buffers;
+        self.current = 0;
+        self.mapping = None;
+        self.size = size;
+        self.padded_bytes_per_row = padded_bytes_per_row;
+    }
+
+    /// Copies the frame drawn into `texture` to a buffer, to be read back once `encoder` has run.
+    pub fn copy(&self, encoder: &mut CommandEncoder, texture: &Texture) {
+        let (width, height) = self.size;
+        encoder.copy_texture_to_buffer(
+            texture.as_image_copy(),
+            wgpu::ImageCopyBuffer {
+                buffer: &self.buffers[self.current],
+                layout: wgpu::ImageDataLayout {
+                    offset: 0,
+                    bytes_per_row: std::num::NonZeroU32::new(self.padded_bytes_per_row),
+                    rows_per_image: None,
+                },
+            },
+            wgpu::Extent3d {
+                width,
+                height,
+                depth_or_array_layers: 1,
+            },
+        );
+    }
+
+    /// Sends the previous frame to the pipe if it has been read back, dropping it if not, and
+    /// starts reading back the one just copied, once `encoder` has been submitted.
+    pub fn send(&mut self, device: &Device) -> Result<()> {
+        // runs the callback if the copy is done, without waiting for it
+        device.poll(wgpu::Maintain::Poll);
+        if let Some((index, mapped)) = self.mapping.take() {
+            let mapped = mapped.try_recv();
+            if let Ok(Ok(())) = mapped {
+                self.read(index);
+            }
+            // also cancels a mapping that hasn't finished
+            self.buffers[index].unmap();
+            if let Ok(Err(e)) = mapped {
+                bail!("couldnt read back frame: {}", e);
+            }
+        }
+
+        let index = self.current;
+        let (sender, receiver) = mpsc::channel();
+        self.buffers[index]
+            .slice(..)
+            .map_async(wgpu::MapMode::Read, move |result| {
+                let _ = sender.send(result);
+            });
+        self.mapping = Some((index, receiver));
+        self.current = 1 - index;
+
+        Ok(())
+    }
+
+    /// Sends the frame in the mapped buffer `index` to the pipe.
+    fn read(&self, index: usize) {
+        let slice = self.buffers[index].slice(..);
+        let row_bytes = self.size.0 as usize * 4;
+        let mut frame = Vec::with_capacity(row_bytes * self.size.1 as usize);
+        for row in slice
+            .get_mapped_range()
+            .chunks(self.padded_bytes_per_row as usize)
+        {
+            frame.extend_from_slice(&row[..row_bytes]);
+        }
+
+        if self.bgra {
+            for pixel in frame.chunks_exact_mut(4) {
+                pixel.swap(0, 2);
+            }
+        }
+
+        // a full queue means the reader is behind, and a closed one that it has gone away
+        let _ = self.sender.try_send(frame);
+    }
+}
+
+/// Two buffers for a frame of `size`, and their row length, which copies need padded to 256
+/// bytes.
+fn create_buffers(device: &Device, (width, height): (u32, u32)) -> ([Buffer; 2], u32) {
+    let align = wgpu::COPY_BYTES_PER_ROW_ALIGNMENT;
+    let padded_bytes_per_row = (width * 4).div_ceil(align) * align;
+
+    let buffers = [(); 2].map(|()| {
+        device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Pipe Buffer"),
+            size: padded_bytes_per_row as u64 * height as u64,
+            usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+            mapped_at_creation: false,
+        })
+    });
+
+    (buffers, padded_bytes_per_row)
+}
@@ -0,0 +1,211 @@
+//! `--compute`: a compute shader run into a texture before the first frame, and again whenever
+//! the size drawn at changes, for work too slow to redo every frame. The shader defines
+//!
+//! ```wgsl
+//! fn precompute(coord: vec2<f32>, size: vec2<f32>) -> vec4<f32>
+//! ```
+//!
+//! which is called once per texel with `coord` in pixels from the bottom left, like `main_image`.
+//! The fragment shader reads the result with `precomputed(uv)`.
+
+use wgpu::{BindGroup, BindGroupLayout, CommandEncoder, ComputePipeline, Device};
+
+const PREFIX: &str = include_str!("./assets/compute.prefix.wgsl");
+const SUFFIX: &str = include_str!("./assets/compute.suffix.wgsl");
+
+/// Matches `@workgroup_size` in the suffix.
+const WORKGROUP_SIZE: u32 = 8;
+
+/// 16 bit float so data like noise or distances keeps its precision, and storage support for it
+/// is guaranteed.
+const FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba16Float;
+
+pub struct Precompute {
+    pipeline: ComputePipeline,
+    storage_bind_group_layout: BindGroupLayout,
+    bind_group_layout: BindGroupLayout,
+    sampler: wgpu::Sampler,
+
+    /// The texture itself is kept alive by the bind groups.
+    size: (u32, u32),
+    storage_bind_group: BindGroup,
+    bind_group: BindGroup,
+    /// Whether the texture has to be computed before it's next read.
+    stale: bool,
+}
+
+impl Precompute {
+    pub fn new(device: &Device, source: &str, size: (u32, u32)) -> Self {
+        let module = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("compute_shader"),
+            source: wgpu::ShaderSource::Wgsl([PREFIX, source, SUFFIX].concat().into()),
+        });
+
+        let storage_bind_group_layout =
+            device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                label: Some("Precompute Storage Bind Group Layout"),
+                entries: &[wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::StorageTexture {
+                        access: wgpu::StorageTextureAccess::WriteOnly,
+                        format: FORMAT,
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                    },
+                    count: None,
+                }],
+            });
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: None,
+            bind_group_layouts: &[&storage_bind_group_layout],
+            push_constant_ranges: &[],
+        });
+        let pipeline = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+            label: Some("Precompute Pipeline"),
+            layout: Some(&pipeline_layout),
+            module: &module,
+            entry_point: "main",
+        });
+
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Precomputed Bind Group Layout"),
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        multisampled: false,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                    count: None,
+                },
+            ],
+        });
+
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some("Precomputed Sampler"),
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            ..Default::default()
+        });
+
+        let (storage_bind_group, bind_group) = create_target(
+            device,
+            &storage_bind_group_layout,
+            &bind_group_layout,
+            &sampler,
+            size,
+        );
+
+        Self {
+            pipeline,
+            storage_bind_group_layout,
+            bind_group_layout,
+            sampler,
+            size,
+            storage_bind_group,
+            bind_group,
+            stale: true,
+        }
+    }
+
+    /// Layout of [`Self::bind_group`], for the fragment shader's pipeline.
+    pub fn bind_group_layout(&self) -> &BindGroupLayout {
+        &self.bind_group_layout
+    }
+
+    pub fn bind_group(&self) -> &BindGroup {
+        &self.bind_group
+    }
+
+    /// Starts over at the new size, to be computed again before the next frame.
+    pub fn resize(&mut self, device: &Device, size: (u32, u32)) {
+        let (storage_bind_group, bind_group) = create_target(
+            device,
+            &self.storage_bind_group_layout,
+            &self.bind_group_layout,
+            &self.sampler,
+            size,
+        );
+        self.size = size;
+        self.storage_bind_group = storage_bind_group;
+        self.bind_group = bind_group;
+        self.stale = true;
+    }
+
+    /// Runs the compute shader as part of `encoder` if the texture is out of date.
+    pub fn run(&mut self, encoder: &mut CommandEncoder) {
+        if !self.stale {
+            return;
+        }
+        self.stale = false;
+
+        let (width, height) = self.size;
+        let mut compute_pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+            label: Some("Precompute Pass"),
+        });
+        compute_pass.set_pipeline(&self.pipeline);
+        compute_pass.set_bind_group(0, &self.storage_bind_group, &[]);
+        compute_pass.dispatch_workgroups(
+            width.div_ceil(WORKGROUP_SIZE),
+            height.div_ceil(WORKGROUP_SIZE),
+            1,
+        );
+    }
+}
+
+fn create_target(
+    device: &Device,
+    storage_layout: &BindGroupLayout,
+    layout: &BindGroupLayout,
+    sampler: &wgpu::Sampler,
+    (width, height): (u32, u32),
+) -> (BindGroup, BindGroup) {
+    let texture = device.create_texture(&wgpu::TextureDescriptor {
+        label: Some("Precomputed"),
+        size: wgpu::Extent3d {
+            width,
+            height,
+            depth_or_array_layers: 1,
+        },
+        mip_level_count: 1,
+        sample_count: 1,
+        dimension: wgpu::TextureDimension::D2,
+        format: FORMAT,
+        usage: wgpu::TextureUsages::STORAGE_BINDING | wgpu::TextureUsages::TEXTURE_BINDING,
+        view_formats: &[],
+    });
+    let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+
+    let storage_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+        label: Some("Precompute Storage Bind Group"),
+        layout: storage_layout,
+        entries: &[wgpu::BindGroupEntry {
+            binding: 0,
+            resource: wgpu::BindingResource::TextureView(&view),
+        }],
+    });
+    let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+        label: Some("Precomputed Bind Group"),
+        layout,
+        entries: &[
+            wgpu::BindGroupEntry {
+                binding: 0,
+                resource: wgpu::BindingResource::TextureView(&view),
+            },
+            wgpu::BindGroupEntry {
+                binding: 1,
+                resource: wgpu::BindingResource::Sampler(sampler),
+            },
+        ],
+    });
+
+    (storage_bind_group, bind_group)
+}
@@ -0,0 +1,95 @@
+//! Sources of custom uniform values that change on their own, polled before every frame. A
+//! provider declares one `custom.<name>` uniform per value it returns, or `custom.<name>_<i>` when
+//! it returns more than one, and must always return the same number of values.
+//!
+//! - `cpu-load` declares `cpu_load`, the share of time all cpus were busy, from 0 to 1
+
+use std::{
+    fs,
+    time::{Duration, Instant},
+};
+
+use crate::config::Provider;
+
+pub trait UniformProvider {
+    fn name(&self) -> &str;
+
+    /// Called before every frame, so anything slow should be cached.
+    fn value(&mut self) -> Vec<f32>;
+}
+
+pub fn create(provider: Provider) -> Box<dyn UniformProvider> {
+    match provider {
+        Provider::CpuLoad => Box::new(CpuLoad::default()),
+    }
+}
+
+/// The uniforms `provider` declares, with their current values.
+pub fn uniforms(provider: &mut dyn UniformProvider) -> Vec<(String, f32)> {
+    let values = provider.value();
+    if let [value] = values[..] {
+        return vec![(provider.name().to_string(), value)];
+    }
+
+    values
+        .into_iter()
+        .enumerate()
+        .map(|(index, value)| (format!("{}_{}", provider.name(), index), value))
+        .collect()
+}
+
+/// Reads `/proc/stat`, at most once per [`CpuLoad::INTERVAL`] since busy time over a single frame
+/// is mostly noise.
+#[derive(Default)]
+pub struct CpuLoad {
+    /// Idle and total time at the last sample.
+    last: Option<(u64, u64)>,
+    sampled: Option<Instant>,
+    load: f32,
+}
+
+impl CpuLoad {
+    const INTERVAL: Duration = Duration::from_secs(1);
+
+    /// Idle and total time of all cpus so far, in clock ticks.
+    fn times() -> Option<(u64, u64)> {
+        let stat = fs::read_to_string("/proc/stat").ok()?;
+        let times = stat
+            .lines()
+            .next()?
+            .strip_prefix("cpu ")?
+            .split_whitespace()
+            .map(|time| time.parse::<u64>().ok())
+            .collect::<Option<Vec<_>>>()?;
+
+        // idle and iowait
+        let idle = times.iter().skip(3).take(2).sum();
+        Some((idle, times.iter().sum()))
+    }
+}
+
+impl UniformProvider for CpuLoad {
+    fn name(&self) -> &str {
+        Provider::CpuLoad.uniform_name()
+    }
+
+    fn value(&mut self) -> Vec<f32> {
+        let due = self
+            .sampled
+            .is_none_or(|sampled| sampled.elapsed() >= Self::INTERVAL);
+        if due {
+            self.sampled = Some(Instant::now());
+            let times = Self::times();
+            if let (Some((last_idle, last_total)), Some((idle, total))) = (self.last, times) {
+                let total = total.saturating_sub(last_total);
+                if total > 0 {
+                    let idle = idle.saturating_sub(last_idle);
+                    self.load = 1.0 - idle as f32 / total as f32;
+                }
+            }
+            self.last = times;
+        }
+
+        vec![self.load]
+    }
+}
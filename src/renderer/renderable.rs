@@ -1,19 +1,45 @@
 use std::time::Instant;
 
 use anyhow::{bail, Result};
-use sctk::output::OutputInfo;
+use log::warn;
 use wgpu::{
-    util::DeviceExt, BindGroup, BindGroupLayout, Buffer, Device, Queue, RenderPipeline,
-    ShaderModule, Surface, SurfaceConfiguration, SurfaceTexture, TextureView,
+    util::DeviceExt, BindGroup, BindGroupLayout, Buffer, Color, Device, Queue, RenderPipeline,
+    ShaderModule, Surface, SurfaceConfiguration, SurfaceError, SurfaceTexture, Texture,
+    TextureFormat, TextureView,
 };
 
-use super::output_surface::OutputSurface;
+use super::{feedback::Feedback, pipe::Capture, precompute::Precompute, shader, upscale::Upscaler};
+use crate::config::Placement;
 
 const UNIFORM_GROUP_ID: u32 = 0;
+const CUSTOM_UNIFORM_BINDING: u32 = 1;
+const FEEDBACK_GROUP_ID: u32 = 1;
+
+/// Weight of the newest sample in the `frame_rate` moving average.
+const FRAME_RATE_SMOOTHING: f32 = 0.05;
+
+const VERT: &str = include_str!("./assets/vertex.wgsl");
+const FRAG_PREFIX: &str = include_str!("./assets/fragment.prefix.wgsl");
+const FRAG_SUFFIX: &str = include_str!("./assets/fragment.suffix.wgsl");
+// uses FEEDBACK_GROUP_ID
+const FEEDBACK_PREFIX: &str = include_str!("./assets/feedback.prefix.wgsl");
+// needs precomputed_texture and precomputed_sampler declared before it
+const PRECOMPUTED_PREFIX: &str = include_str!("./assets/precomputed.prefix.wgsl");
+
+/// Textures the shader reads besides the uniforms, each in its own bind group after them in this
+/// order.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Inputs {
+    /// `--feedback`
+    pub feedback: bool,
+    /// `--compute`
+    pub precomputed: bool,
+}
 
-const VERT: &'static str = include_str!("./assets/vertex.wgsl");
-const FRAG_PREFIX: &'static str = include_str!("./assets/fragment.prefix.wgsl");
-const FRAG_SUFFIX: &'static str = include_str!("./assets/fragment.suffix.wgsl");
+/// The bind group `--compute`'s output is in, right after the feedback one when there is one.
+fn precomputed_group(feedback: bool) -> u32 {
+    FEEDBACK_GROUP_ID + feedback as u32
+}
 
 pub struct RenderConfig {
     pub frag_shader: ShaderModule,
@@ -21,19 +47,65 @@ pub struct RenderConfig {
 }
 
 impl RenderConfig {
-    pub fn new(output_surface: &OutputSurface, shader_source: &str) -> Result<Self> {
-        let mut frag_shader_source =
-            String::with_capacity(FRAG_PREFIX.len() + shader_source.len() + FRAG_SUFFIX.len());
+    pub fn new(
+        device: &Device,
+        shader_source: &str,
+        custom_uniforms: &[(String, f32)],
+        transparent: bool,
+        coord_scale: f32,
+        inputs: Inputs,
+        encode_srgb: bool,
+    ) -> Result<Self> {
+        let mut custom_prefix = custom_uniforms_prefix(custom_uniforms);
+        if inputs.feedback {
+            custom_prefix.push_str(FEEDBACK_PREFIX);
+        }
+        if inputs.precomputed {
+            custom_prefix.push_str(&format!(
+                "@group({0}) @binding(0)\nvar precomputed_texture: texture_2d<f32>;\n@group({0}) @binding(1)\nvar precomputed_sampler: sampler;\n\n",
+                precomputed_group(inputs.feedback)
+            ));
+            custom_prefix.push_str(PRECOMPUTED_PREFIX);
+        }
+        // read by the suffix: opaque wallpapers ignore whatever alpha the shader returns,
+        // frag_coord is scaled to match `resolution` when that isn't the size being drawn at, and
+        // colors are encoded to sRGB by hand when the surface won't do it
+        let constants = format!(
+            "const TRANSPARENT: bool = {};\nconst COORD_SCALE: f32 = {:?};\nconst ENCODE_SRGB: bool = {};\n\n",
+            transparent, coord_scale, encode_srgb
+        );
+
+        // naga only says a name is defined twice, point at the line that's the shader's fault
+        let generated = [FRAG_PREFIX, &constants, &custom_prefix, FRAG_SUFFIX].concat();
+        let reserved = shader::declarations(&generated);
+        for (line, name) in shader::declarations(shader_source) {
+            if reserved.iter().any(|(_, reserved)| *reserved == name) {
+                warn!(
+                    "line {} of the shader declares {}, which glpaper already declares, rename it",
+                    line, name
+                );
+            }
+        }
+
+        let mut frag_shader_source = String::with_capacity(
+            FRAG_PREFIX.len()
+                + constants.len()
+                + custom_prefix.len()
+                + shader_source.len()
+                + FRAG_SUFFIX.len(),
+        );
         frag_shader_source.push_str(FRAG_PREFIX);
+        frag_shader_source.push_str(&constants);
+        frag_shader_source.push_str(&custom_prefix);
         frag_shader_source.push_str(shader_source);
         frag_shader_source.push_str(FRAG_SUFFIX);
 
-        let frag_shader = output_surface.create_shader_module(wgpu::ShaderModuleDescriptor {
+        let frag_shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
             label: Some("fragment_shader"),
             source: wgpu::ShaderSource::Wgsl(frag_shader_source.into()),
         });
 
-        let vert_shader = output_surface.create_shader_module(wgpu::ShaderModuleDescriptor {
+        let vert_shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
             label: Some("vertex_shader"),
             source: wgpu::ShaderSource::Wgsl(VERT.into()),
         });
@@ -45,11 +117,92 @@ impl RenderConfig {
     }
 }
 
+/// Builds the pipeline drawing `config` into a `format` target. Needs no surface, so shaders can be
+/// checked without an output to draw on.
+pub fn create_pipeline(
+    device: &Device,
+    config: &RenderConfig,
+    bind_group_layouts: &[&BindGroupLayout],
+    format: TextureFormat,
+    sample_count: u32,
+    blend: wgpu::BlendState,
+) -> RenderPipeline {
+    let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+        label: None,
+        bind_group_layouts,
+        push_constant_ranges: &[],
+    });
+
+    device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+        label: None,
+        layout: Some(&pipeline_layout),
+        vertex: wgpu::VertexState {
+            module: &config.vert_shader,
+            entry_point: "main",
+            buffers: &[],
+        },
+        fragment: Some(wgpu::FragmentState {
+            module: &config.frag_shader,
+            entry_point: "main",
+            targets: &[Some(wgpu::ColorTargetState {
+                format,
+                blend: Some(blend),
+                write_mask: wgpu::ColorWrites::ALL,
+            })],
+        }),
+        primitive: wgpu::PrimitiveState::default(),
+        depth_stencil: None,
+        multisample: wgpu::MultisampleState {
+            count: sample_count,
+            ..Default::default()
+        },
+        multiview: None,
+    })
+}
+
+/// Declares the `--uniform` values as members of a `custom` uniform struct, so a shader reads
+/// `--uniform exposure=1.5` as `custom.exposure`.
+fn custom_uniforms_prefix(custom_uniforms: &[(String, f32)]) -> String {
+    if custom_uniforms.is_empty() {
+        return String::new();
+    }
+
+    let mut prefix = String::from("struct CustomUniforms {\n");
+    for (name, _) in custom_uniforms {
+        prefix.push_str(&format!("    {}: f32,\n", name));
+    }
+    prefix.push_str(&format!(
+        "}};\n\n@group({}) @binding({})\nvar<uniform> custom: CustomUniforms;\n\n",
+        UNIFORM_GROUP_ID, CUSTOM_UNIFORM_BINDING
+    ));
+
+    prefix
+}
+
+/// Pointer input, in pixels with the origin at the bottom left like `frag_coord`.
+#[derive(Clone, Copy, Debug)]
+pub enum MouseEvent {
+    Motion([f32; 2]),
+    Press([f32; 2]),
+    Release([f32; 2]),
+    /// Wheel steps, up and right positive.
+    Scroll([f32; 2]),
+}
+
 pub struct Renderable {
     pipeline: RenderPipeline,
 
     surface_configuration: SurfaceConfiguration,
     render_state: RenderState,
+    clear_color: Color,
+    upscaler: Option<Upscaler>,
+    multisample: Option<Multisample>,
+    feedback: Option<Feedback>,
+    precompute: Option<Precompute>,
+    capture: Option<Capture>,
+    solid: bool,
+    /// `--overlay` pipelines, drawn over `pipeline` in order.
+    overlays: Vec<RenderPipeline>,
 
     surface_texture: Option<SurfaceTexture>,
     texture_view: Option<TextureView>,
@@ -60,22 +213,57 @@ impl Renderable {
         pipeline: RenderPipeline,
         surface_configuration: SurfaceConfiguration,
         render_state: RenderState,
+        clear_color: Color,
+        upscaler: Option<Upscaler>,
+        multisample: Option<Multisample>,
+        feedback: Option<Feedback>,
     ) -> Result<Self> {
+        // the surface is composited as premultiplied when transparent
+        let clear_color = Color {
+            r: clear_color.r * clear_color.a,
+            g: clear_color.g * clear_color.a,
+            b: clear_color.b * clear_color.a,
+            a: clear_color.a,
+        };
+
         Ok(Self {
             pipeline,
             surface_configuration,
             render_state,
+            clear_color,
+            upscaler,
+            multisample,
+            feedback,
+            precompute: None,
+            capture: None,
+            solid: false,
+            overlays: Vec::new(),
             surface_texture: None,
             texture_view: None,
         })
     }
 
-    pub fn frame_start(&mut self, surface: &mut Surface) -> Result<()> {
+    /// Acquires the next surface texture. Returns `false` if none became available in time, in
+    /// which case the frame should be skipped.
+    pub fn frame_start(&mut self, surface: &mut Surface, device: &Device) -> Result<bool> {
         if self.surface_texture.is_some() {
             bail!("Non-finished wgpu::SurfaceTexture found.")
         }
 
-        let surface_texture = surface.get_current_texture().expect("couldnt get texture");
+        let surface_texture = match surface.get_current_texture() {
+            Ok(surface_texture) => surface_texture,
+            // the compositor has stopped taking frames from us, usually because the output is
+            // covered. don't wedge waiting on it, just try again next frame.
+            Err(SurfaceError::Timeout) => {
+                warn!("timed out waiting for a surface texture, skipping frame");
+                return Ok(false);
+            }
+            Err(SurfaceError::Outdated | SurfaceError::Lost) => {
+                surface.configure(device, &self.surface_configuration);
+                surface.get_current_texture()?
+            }
+            Err(e) => return Err(e.into()),
+        };
 
         self.surface_texture = Some(surface_texture);
 
@@ -88,35 +276,57 @@ impl Renderable {
             ));
         }
 
-        Ok(())
+        Ok(true)
     }
 
-    pub fn render(&mut self, device: &mut Device, queue: &mut Queue) -> Result<()> {
+    pub fn render(&mut self, device: &Device, queue: &Queue) -> Result<()> {
         if self.texture_view.is_none() {
             bail!("No actived wgpu::TextureView found.")
         }
 
-        let view = self.texture_view.as_ref().unwrap();
+        let surface_view = self.texture_view.as_ref().unwrap();
+        // with --render-scale the shader draws offscreen, and that is stretched over the surface
+        let target = match self.upscaler {
+            Some(ref upscaler) => upscaler.view(),
+            None => surface_view,
+        };
+        // with --msaa the shader draws into the multisampled texture, resolved into the target
+        let (view, resolve_target) = match self.multisample {
+            Some(ref multisample) => (&multisample.view, Some(target)),
+            None => (target, None),
+        };
 
         let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
             label: Some("Render Encoder"),
         });
         self.render_state.update_time();
 
+        if let Some(ref mut precompute) = self.precompute {
+            precompute.run(&mut encoder);
+        }
+
         queue.write_buffer(
             &self.render_state.uniform_buffer,
             0,
             self.render_state.as_bytes(),
         );
 
+        if let Some(ref custom_buffer) = self.render_state.custom_buffer {
+            queue.write_buffer(
+                custom_buffer,
+                0,
+                bytemuck::cast_slice(&self.render_state.custom_values),
+            );
+        }
+
         {
             let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
                 label: Some("Render Pass"),
                 color_attachments: &[Some(wgpu::RenderPassColorAttachment {
                     view,
-                    resolve_target: None,
+                    resolve_target,
                     ops: wgpu::Operations {
-                        load: wgpu::LoadOp::Load,
+                        load: wgpu::LoadOp::Clear(self.clear_color),
                         store: true,
                     },
                 })],
@@ -142,6 +352,16 @@ impl Renderable {
                 &self.render_state.uniform_bind_group,
                 &[],
             );
+            if let Some(ref feedback) = self.feedback {
+                render_pass.set_bind_group(FEEDBACK_GROUP_ID, feedback.bind_group(), &[]);
+            }
+            if let Some(ref precompute) = self.precompute {
+                render_pass.set_bind_group(
+                    precomputed_group(self.feedback.is_some()),
+                    precompute.bind_group(),
+                    &[],
+                );
+            }
 
             //let mut index = 1;
             //for (_, bind_group) in &self.texture_bind_groups {
@@ -149,14 +369,142 @@ impl Renderable {
             //    index += 1;
             //}
 
-            render_pass.draw(0..3, 0..1);
+            // with --color the clear is the whole frame
+            if !self.solid {
+                render_pass.draw(0..3, 0..1);
+            }
+            // same bind groups, the layouts are shared
+            for overlay in &self.overlays {
+                render_pass.set_pipeline(overlay);
+                render_pass.draw(0..3, 0..1);
+            }
+        }
+
+        if let Some(ref upscaler) = self.upscaler {
+            // there is always an upscaler with feedback or --pipe, the frame has to be somewhere
+            // it can be copied from
+            if let Some(ref feedback) = self.feedback {
+                feedback.store(&mut encoder, upscaler.texture());
+            }
+            if let Some(ref capture) = self.capture {
+                capture.copy(&mut encoder, upscaler.texture());
+            }
+            upscaler.draw(&mut encoder, surface_view, self.clear_color);
         }
 
         queue.submit(Some(encoder.finish()));
+        self.render_state.next_frame();
+
+        if let Some(ref mut capture) = self.capture {
+            capture.send(device)?;
+        }
 
         Ok(())
     }
 
+    /// Sends every frame drawn from now on to `--pipe`. Only works when drawing offscreen.
+    pub fn set_capture(&mut self, capture: Option<Capture>) {
+        self.capture = capture;
+    }
+
+    /// Reads `--compute`'s output from now on, computed before the next frame.
+    pub fn set_precompute(&mut self, precompute: Option<Precompute>) {
+        self.precompute = precompute;
+    }
+
+    pub fn set_overlays(&mut self, overlays: Vec<RenderPipeline>) {
+        self.overlays = overlays;
+    }
+
+    /// Only clears to the clear color from now on, without drawing the shader.
+    pub fn set_solid(&mut self, solid: bool) {
+        self.solid = solid;
+    }
+
+    pub fn set_paused(&mut self, paused: bool) {
+        if paused {
+            self.render_state.pause();
+        } else {
+            self.render_state.resume();
+        }
+    }
+
+    pub fn seek(&mut self, time: f32) {
+        self.render_state.seek(time);
+    }
+
+    pub fn resize(&mut self, surface: &Surface, device: &Device, width: u32, height: u32) {
+        self.surface_configuration.width = width;
+        self.surface_configuration.height = height;
+        surface.configure(device, &self.surface_configuration);
+
+        let (render_size, resolution) = match self.upscaler {
+            Some(ref mut upscaler) => {
+                upscaler.resize(device, width, height);
+                (
+                    upscaler.render_size((width, height)),
+                    upscaler.resolution((width, height)),
+                )
+            }
+            None => ((width, height), (width, height)),
+        };
+        if let Some(ref mut multisample) = self.multisample {
+            multisample.resize(device, render_size);
+        }
+        if let Some(ref mut feedback) = self.feedback {
+            feedback.resize(device, render_size);
+        }
+        if let Some(ref mut precompute) = self.precompute {
+            precompute.resize(device, render_size);
+        }
+        if let Some(ref mut capture) = self.capture {
+            capture.resize(device, render_size);
+        }
+        self.render_state.set_resolution(resolution.0, resolution.1);
+    }
+
+    pub fn frame_time(&self) -> f32 {
+        self.render_state.frame_duration
+    }
+
+    /// Where the frame is drawn before it's stretched over the surface, if it isn't drawn on the
+    /// surface directly.
+    pub fn upscaler(&self) -> Option<&Upscaler> {
+        self.upscaler.as_ref()
+    }
+
+    pub fn surface_configuration(&self) -> &SurfaceConfiguration {
+        &self.surface_configuration
+    }
+
+    /// `--color`, premultiplied when transparent.
+    pub fn clear_color(&self) -> Color {
+        self.clear_color
+    }
+
+    /// Sets the custom uniform at `index`. One the pipeline wasn't built with is ignored.
+    pub fn set_custom_uniform(&mut self, index: usize, value: f32) {
+        if let Some(custom_value) = self.render_state.custom_values.get_mut(index) {
+            *custom_value = value;
+        }
+    }
+
+    pub fn mouse(&mut self, event: MouseEvent) {
+        self.render_state.mouse(event);
+    }
+
+    pub fn set_power(&mut self, power: [f32; 2]) {
+        self.render_state.uniform.power = power;
+    }
+
+    pub fn set_seed(&mut self, seed: u32) {
+        self.render_state.uniform.seed = seed as f32;
+    }
+
+    pub fn set_refresh_rate(&mut self, refresh_rate: f32) {
+        self.render_state.uniform.refresh_rate = refresh_rate;
+    }
+
     pub fn frame_finish(&mut self) -> Result<()> {
         if self.surface_texture.is_none() {
             bail!("No actived wgpu::SurfaceTexture found.")
@@ -170,8 +518,72 @@ impl Renderable {
     }
 }
 
+/// Multisampled texture for `--msaa`, drawn into instead of the real target.
+pub struct Multisample {
+    sample_count: u32,
+    format: TextureFormat,
+    // only kept so the texture lives as long as the view
+    _texture: Texture,
+    view: TextureView,
+}
+
+impl Multisample {
+    pub fn new(
+        device: &Device,
+        format: TextureFormat,
+        size: (u32, u32),
+        sample_count: u32,
+    ) -> Self {
+        let (texture, view) = create_multisampled(device, format, size, sample_count);
+        Self {
+            sample_count,
+            format,
+            _texture: texture,
+            view,
+        }
+    }
+
+    pub fn resize(&mut self, device: &Device, size: (u32, u32)) {
+        let (texture, view) = create_multisampled(device, self.format, size, self.sample_count);
+        self._texture = texture;
+        self.view = view;
+    }
+}
+
+fn create_multisampled(
+    device: &Device,
+    format: TextureFormat,
+    (width, height): (u32, u32),
+    sample_count: u32,
+) -> (Texture, TextureView) {
+    let texture = device.create_texture(&wgpu::TextureDescriptor {
+        label: Some("Multisampled Target"),
+        size: wgpu::Extent3d {
+            width,
+            height,
+            depth_or_array_layers: 1,
+        },
+        mip_level_count: 1,
+        sample_count,
+        dimension: wgpu::TextureDimension::D2,
+        format,
+        usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
+        view_formats: &[],
+    });
+    let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+
+    (texture, view)
+}
+
 pub struct RenderState {
     time_instant: Instant,
+    paused_at: Option<Instant>,
+    // time is time_offset + time_scale * the seconds since time_instant
+    time_offset: f32,
+    time_scale: f32,
+    // unscaled seconds since time_instant at the last frame, and how long that frame took
+    elapsed: f32,
+    frame_duration: f32,
 
     uniform_bind_group: BindGroup,
     // TODO: does this need to be public...?
@@ -179,14 +591,29 @@ pub struct RenderState {
 
     uniform: Uniform,
     uniform_buffer: Buffer,
+
+    custom_values: Vec<f32>,
+    custom_buffer: Option<Buffer>,
 }
 
 impl RenderState {
-    pub fn new(device: &Device, output_info: &OutputInfo) -> Self {
-        let mut uniform = Uniform::default();
-
-        let (width, height) = output_info.logical_size.expect("illogical size?");
-        uniform.resolution = [width as f32, height as f32];
+    pub fn new(
+        device: &Device,
+        (width, height): (u32, u32),
+        custom_uniforms: &[(String, f32)],
+        time_scale: f32,
+        epoch: Option<Instant>,
+        placement: Placement,
+    ) -> Self {
+        let mut uniform = Uniform {
+            resolution: [width as f32, height as f32],
+            output: [placement.index, placement.count],
+            output_offset: placement.offset,
+            virtual_resolution: placement.virtual_size,
+            // full and plugged in until told otherwise, so shaders that dim on battery don't
+            power: [1.0, 1.0],
+            ..Default::default()
+        };
 
         let uniform_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
             label: Some("Uniform Buffer"),
@@ -194,43 +621,138 @@ impl RenderState {
             usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
         });
 
+        let mut custom_values: Vec<f32> = custom_uniforms.iter().map(|(_, value)| *value).collect();
+        // keep the buffer a multiple of 16 bytes, as uniform structs are padded to
+        custom_values.resize(custom_values.len().div_ceil(4) * 4, 0.0);
+
+        let custom_buffer = if custom_values.is_empty() {
+            None
+        } else {
+            Some(
+                device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                    label: Some("Custom Uniform Buffer"),
+                    contents: bytemuck::cast_slice(&custom_values),
+                    usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+                }),
+            )
+        };
+
+        let uniform_layout_entry = |binding| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::FRAGMENT,
+            ty: wgpu::BindingType::Buffer {
+                ty: wgpu::BufferBindingType::Uniform,
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            count: None,
+        };
+
+        let mut layout_entries = vec![uniform_layout_entry(0)];
+        let mut entries = vec![wgpu::BindGroupEntry {
+            binding: 0,
+            resource: uniform_buffer.as_entire_binding(),
+        }];
+
+        if let Some(ref custom_buffer) = custom_buffer {
+            layout_entries.push(uniform_layout_entry(CUSTOM_UNIFORM_BINDING));
+            entries.push(wgpu::BindGroupEntry {
+                binding: CUSTOM_UNIFORM_BINDING,
+                resource: custom_buffer.as_entire_binding(),
+            });
+        }
+
         let uniform_bind_group_layout =
             device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
                 label: Some("Uniform Bind Group Layout"),
-                entries: &[wgpu::BindGroupLayoutEntry {
-                    binding: 0,
-                    visibility: wgpu::ShaderStages::FRAGMENT,
-                    ty: wgpu::BindingType::Buffer {
-                        ty: wgpu::BufferBindingType::Uniform,
-                        has_dynamic_offset: false,
-                        min_binding_size: None,
-                    },
-                    count: None,
-                }],
+                entries: &layout_entries,
             });
 
         let uniform_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
             label: Some("Uniform Bind Group"),
             layout: &uniform_bind_group_layout,
-            entries: &[wgpu::BindGroupEntry {
-                binding: 0,
-                resource: uniform_buffer.as_entire_binding(),
-            }],
+            entries: &entries,
         });
 
-        let time_instant = Instant::now();
+        // with a shared epoch time carries on from it rather than starting over at 0
+        let time_instant = epoch.unwrap_or_else(Instant::now);
+        let elapsed = time_instant.elapsed().as_secs_f32();
+        uniform.time = elapsed * time_scale;
 
         Self {
             time_instant,
+            paused_at: None,
+            time_offset: 0.0,
+            time_scale,
+            elapsed,
+            frame_duration: 0.0,
             uniform_bind_group,
             uniform_bind_group_layout,
             uniform,
             uniform_buffer,
+            custom_values,
+            custom_buffer,
         }
     }
 
+    pub fn set_resolution(&mut self, width: u32, height: u32) {
+        self.uniform.resolution = [width as f32, height as f32];
+    }
+
+    /// Stops `time` from advancing until `resume` is called.
+    pub fn pause(&mut self) {
+        self.paused_at.get_or_insert_with(Instant::now);
+    }
+
+    pub fn resume(&mut self) {
+        if let Some(paused_at) = self.paused_at.take() {
+            // shift the start forward so the time spent paused never happened
+            self.time_instant += paused_at.elapsed();
+        }
+    }
+
+    /// Jumps the clock to `time` and carries on from there. `time_delta` of the next frame only
+    /// covers the time since the seek.
+    pub fn seek(&mut self, time: f32) {
+        self.time_instant = self.paused_at.unwrap_or_else(Instant::now);
+        self.time_offset = time;
+        self.elapsed = 0.0;
+        self.uniform.time = time;
+    }
+
     pub fn update_time(&mut self) {
-        self.uniform.time = self.time_instant.elapsed().as_secs_f32();
+        if self.paused_at.is_some() {
+            return;
+        }
+
+        let elapsed = self.time_instant.elapsed().as_secs_f32();
+        self.frame_duration = elapsed - self.elapsed;
+        self.elapsed = elapsed;
+
+        let time = self.time_offset + elapsed * self.time_scale;
+        self.uniform.time_delta = time - self.uniform.time;
+
+        // the frame rate is real frames per second, whatever the time scale
+        if self.frame_duration > 0.0 {
+            let frame_rate = 1.0 / self.frame_duration;
+            self.uniform.frame_rate = if self.uniform.frame_rate == 0.0 {
+                frame_rate
+            } else {
+                self.uniform.frame_rate
+                    + FRAME_RATE_SMOOTHING * (frame_rate - self.uniform.frame_rate)
+            };
+        }
+
+        self.uniform.time = time;
+    }
+
+    /// Advances `frame`, which counts up from 0 on the first frame a pipeline draws.
+    pub fn next_frame(&mut self) {
+        self.uniform.frame = self.uniform.frame.wrapping_add(1);
+    }
+
+    pub fn mouse(&mut self, event: MouseEvent) {
+        self.uniform.mouse(event);
     }
 
     pub fn as_bytes(&self) -> &[u8] {
@@ -248,11 +770,132 @@ pub struct Uniform {
     pub mouse_release: [f32; 2],
     pub resolution: [f32; 2],
     pub time: f32,
+    pub frame_rate: f32,
+    pub frame: u32,
+    pub time_delta: f32,
+    /// Index of this output and how many outputs there are.
+    pub output: [u32; 2],
+    pub audio: [f32; 4],
+    pub output_offset: [f32; 2],
+    pub virtual_resolution: [f32; 2],
+    /// Battery charge and whether on external power, see `--power-uniform`.
+    pub power: [f32; 2],
+    /// Of the output's current mode, in Hz.
+    pub refresh_rate: f32,
     _padding1: u32,
+    /// Scrolled so far, in wheel steps.
+    pub scroll: [f32; 2],
+    pub seed: f32,
+    _padding2: u32,
 }
 
+// uniform buffers are read in 16 byte chunks, the struct must not end partway through one
+const _: () = assert!(std::mem::size_of::<Uniform>().is_multiple_of(16));
+
 impl Uniform {
     pub fn as_bytes(&self) -> &[u8] {
         bytemuck::bytes_of(self)
     }
+
+    /// Follows Shadertoy's `iMouse`: `cursor` only moves while the button is held, and
+    /// `mouse_press` keeps the last click position, positive while held and negated once released.
+    pub fn mouse(&mut self, event: MouseEvent) {
+        match event {
+            MouseEvent::Motion(position) => {
+                if self.mouse_down != 0 {
+                    self.cursor = position;
+                }
+            }
+            MouseEvent::Press(position) => {
+                self.mouse_down = 1;
+                self.cursor = position;
+                self.mouse_press = position;
+            }
+            MouseEvent::Release(position) => {
+                if self.mouse_down == 0 {
+                    return;
+                }
+                self.mouse_down = 0;
+                self.mouse_release = position;
+                self.mouse_press = self.mouse_press.map(|c| -c.abs());
+            }
+            MouseEvent::Scroll([x, y]) => {
+                self.scroll[0] += x;
+                self.scroll[1] += y;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::mem::{offset_of, size_of};
+
+    use super::*;
+
+    /// The offsets WGSL gives the members of `Uniforms` in fragment.prefix.wgsl.
+    #[test]
+    fn uniform_layout() {
+        assert_eq!(size_of::<Uniform>(), 128);
+
+        macro_rules! assert_offset {
+            ($member:ident, $offset:expr) => {
+                assert_eq!(offset_of!(Uniform, $member), $offset, stringify!($member));
+            };
+        }
+        assert_offset!(cursor, 0);
+        assert_offset!(mouse_down, 8);
+        assert_offset!(mouse_press, 16);
+        assert_offset!(mouse_release, 24);
+        assert_offset!(resolution, 32);
+        assert_offset!(time, 40);
+        assert_offset!(frame_rate, 44);
+        assert_offset!(frame, 48);
+        assert_offset!(time_delta, 52);
+        assert_offset!(output, 56);
+        assert_offset!(audio, 64);
+        assert_offset!(output_offset, 80);
+        assert_offset!(virtual_resolution, 88);
+        assert_offset!(power, 96);
+        assert_offset!(refresh_rate, 104);
+        assert_offset!(scroll, 112);
+        assert_offset!(seed, 120);
+    }
+
+    #[test]
+    fn mouse() {
+        let mut uniform = Uniform::default();
+
+        uniform.mouse(MouseEvent::Motion([5.0, 5.0]));
+        assert_eq!(uniform.cursor, [0.0, 0.0], "cursor moved without a press");
+
+        uniform.mouse(MouseEvent::Press([10.0, 20.0]));
+        assert_eq!(uniform.mouse_down, 1);
+        assert_eq!(uniform.cursor, [10.0, 20.0]);
+        assert_eq!(uniform.mouse_press, [10.0, 20.0]);
+
+        // dragging moves the cursor but not where the press was
+        uniform.mouse(MouseEvent::Motion([15.0, 25.0]));
+        assert_eq!(uniform.cursor, [15.0, 25.0]);
+        assert_eq!(uniform.mouse_press, [10.0, 20.0]);
+
+        uniform.mouse(MouseEvent::Release([30.0, 40.0]));
+        assert_eq!(uniform.mouse_down, 0);
+        assert_eq!(uniform.cursor, [15.0, 25.0]);
+        assert_eq!(uniform.mouse_press, [-10.0, -20.0]);
+        assert_eq!(uniform.mouse_release, [30.0, 40.0]);
+
+        // nothing moves until the next press, and a stray release is ignored
+        uniform.mouse(MouseEvent::Motion([50.0, 50.0]));
+        uniform.mouse(MouseEvent::Release([60.0, 60.0]));
+        assert_eq!(uniform.cursor, [15.0, 25.0]);
+        assert_eq!(uniform.mouse_press, [-10.0, -20.0]);
+        assert_eq!(uniform.mouse_release, [30.0, 40.0]);
+
+        uniform.mouse(MouseEvent::Press([70.0, 80.0]));
+        assert_eq!(uniform.mouse_down, 1);
+        assert_eq!(uniform.cursor, [70.0, 80.0]);
+        assert_eq!(uniform.mouse_press, [70.0, 80.0]);
+        assert_eq!(uniform.mouse_release, [30.0, 40.0]);
+    }
 }
@@ -0,0 +1,509 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{anyhow, bail, Context, Result};
+use log::warn;
+
+use crate::config;
+
+pub const DEFAULT_FRAG: &str = include_str!("./assets/default.wgsl");
+
+/// Shaders bundled with glpaper, which can be given by name in place of a path.
+pub const EXAMPLES: &[(&str, &str)] = &[
+    ("default", DEFAULT_FRAG),
+    ("plasma", include_str!("./assets/examples/plasma.wgsl")),
+    ("rings", include_str!("./assets/examples/rings.wgsl")),
+];
+
+/// Reads the user's `main_image` source, or the bundled default when no path is given. A path
+/// that doesn't exist is looked up in [`EXAMPLES`].
+///
+/// `#ifdef NAME`, `#ifndef NAME`, `#else` and `#endif` lines keep or drop what they enclose
+/// depending on whether NAME is in `defines`, and each defined name in the kept code is replaced by
+/// its value. A kept line of the form `#include "file.wgsl"` is replaced by that file, resolved
+/// relative to the file doing the including and handled the same way. An `#include` in a dropped
+/// branch is never read.
+pub fn load_fragment_shader(path: Option<&Path>, defines: &[(String, String)]) -> Result<String> {
+    match path {
+        Some(path) if !path.exists() => {
+            let example = EXAMPLES
+                .iter()
+                .find(|(name, _)| path.to_str() == Some(*name));
+            match example {
+                Some((_, source)) => apply_defines(source, defines),
+                None => bail!(
+                    "no shader file or example called {}, see --list-examples",
+                    path.display()
+                ),
+            }
+        }
+        Some(path) => {
+            let mut source = String::new();
+            include(path, defines, &mut Vec::new(), &mut source)?;
+            Ok(source)
+        }
+        None => apply_defines(DEFAULT_FRAG, defines),
+    }
+}
+
+/// Reads a `--compute` shader, preprocessed like [`load_fragment_shader`]. There are no bundled
+/// compute shaders, so it has to be a file.
+pub fn load_compute_shader(path: &Path, defines: &[(String, String)]) -> Result<String> {
+    if !path.exists() {
+        bail!("no compute shader file called {}", path.display());
+    }
+
+    let mut source = String::new();
+    include(path, defines, &mut Vec::new(), &mut source)?;
+    Ok(source)
+}
+
+/// Appends `path` to `out`, preprocessed. `stack` holds the files currently being expanded, to
+/// catch a file including itself.
+fn include(
+    path: &Path,
+    defines: &[(String, String)],
+    stack: &mut Vec<PathBuf>,
+    out: &mut String,
+) -> Result<()> {
+    let source = fs::read_to_string(path)
+        .with_context(|| format!("couldnt read shader {}", path.display()))?;
+    let canonical = fs::canonicalize(path)?;
+    if stack.contains(&canonical) {
+        bail!("{} includes itself", path.display());
+    }
+
+    stack.push(canonical);
+    let dir = path.parent().unwrap_or(Path::new(""));
+    preprocess(&source, defines, out, &mut |target, out| {
+        include(&dir.join(target), defines, stack, out)
+    })
+    .with_context(|| format!("in {}", path.display()))?;
+    stack.pop();
+
+    Ok(())
+}
+
+/// The file named by an `#include` line.
+fn include_target(line: &str) -> Result<&str> {
+    line.trim()
+        .strip_prefix("#include")
+        .map(str::trim)
+        .and_then(|target| target.strip_prefix('"'))
+        .and_then(|target| target.strip_suffix('"'))
+        .filter(|target| !target.is_empty())
+        .ok_or_else(|| anyhow!("expected #include \"file\", got {:?}", line.trim()))
+}
+
+/// Preprocesses `source`, which isn't a file and so can't `#include` anything.
+fn apply_defines(source: &str, defines: &[(String, String)]) -> Result<String> {
+    let mut out = String::with_capacity(source.len());
+    preprocess(source, defines, &mut out, &mut |_, _| {
+        bail!("#include only works in shader files")
+    })?;
+    Ok(out)
+}
+
+/// Appends `source` to `out`, evaluating the `#ifdef` family of lines and substituting `defines`
+/// into the lines that are kept. Each kept `#include` line is handed to `include` in its place.
+/// Dropped lines are left blank, so as long as nothing is included, line numbers in `out` match
+/// those in `source`.
+fn preprocess(
+    source: &str,
+    defines: &[(String, String)],
+    out: &mut String,
+    include: &mut dyn FnMut(&str, &mut String) -> Result<()>,
+) -> Result<()> {
+    // for each open #ifdef, whether its current branch is kept
+    let mut branches: Vec<bool> = Vec::new();
+
+    for (number, line) in source.lines().enumerate() {
+        let number = number + 1;
+        let active = branches.iter().all(|kept| *kept);
+        let mut words = line.split_whitespace();
+
+        match words.next() {
+            Some(directive @ ("#ifdef" | "#ifndef")) => {
+                let name = words
+                    .next()
+                    .ok_or_else(|| anyhow!("line {}: {} needs a name", number, directive))?;
+                let defined = defines.iter().any(|(n, _)| n == name);
+                branches.push(defined == (directive == "#ifdef"));
+            }
+            Some("#else") => match branches.last_mut() {
+                Some(kept) => *kept = !*kept,
+                None => bail!("line {}: #else without #ifdef", number),
+            },
+            Some("#endif") => {
+                branches
+                    .pop()
+                    .ok_or_else(|| anyhow!("line {}: #endif without #ifdef", number))?;
+            }
+            Some(word) if active && word.starts_with("#include") => {
+                include_target(line)
+                    .and_then(|target| include(target, out))
+                    .with_context(|| format!("line {}", number))?;
+                continue;
+            }
+            _ if active => out.push_str(&substitute(line, defines)),
+            _ => {}
+        }
+        out.push('\n');
+    }
+
+    if !branches.is_empty() {
+        bail!("{} #ifdef not closed with #endif", branches.len());
+    }
+
+    Ok(())
+}
+
+/// A `// @param name min max default` line in a shader, which declares `custom.<name>` without
+/// having to pass `--uniform`. `set` keeps the value between `min` and `max`.
+pub struct Param {
+    pub name: String,
+    pub min: f32,
+    pub max: f32,
+    pub default: f32,
+}
+
+/// The `@param` annotations in `source`. Malformed ones are skipped with a warning.
+pub fn params(source: &str) -> Vec<Param> {
+    source
+        .lines()
+        .enumerate()
+        .filter_map(|(number, line)| {
+            let annotation = line
+                .trim()
+                .strip_prefix("//")?
+                .trim()
+                .strip_prefix("@param")?;
+            match parse_param(annotation) {
+                Ok(param) => Some(param),
+                Err(e) => {
+                    warn!("line {}: ignoring @param: {}", number + 1, e);
+                    None
+                }
+            }
+        })
+        .collect()
+}
+
+fn parse_param(annotation: &str) -> Result<Param> {
+    let words: Vec<_> = annotation.split_whitespace().collect();
+    let (name, min, max, default) = match words[..] {
+        [name, min, max, default] => (name, min, max, default),
+        _ => bail!(
+            "expected `@param name min max default`, got {:?}",
+            annotation.trim()
+        ),
+    };
+    if !config::is_identifier(name) {
+        bail!("{:?} isn't a valid name", name);
+    }
+
+    let number = |word: &str| {
+        word.parse::<f32>()
+            .with_context(|| format!("{:?} isn't a number", word))
+    };
+    let (min, max, default) = (number(min)?, number(max)?, number(default)?);
+    if !(min <= default && default <= max) {
+        bail!("{} isn't between {} and {}", default, min, max);
+    }
+
+    Ok(Param {
+        name: name.to_string(),
+        min,
+        max,
+        default,
+    })
+}
+
+/// `uniforms` followed by each of `params` not already among them, at its default.
+pub fn with_params(uniforms: &[(String, f32)], params: &[Param]) -> Vec<(String, f32)> {
+    let mut uniforms = uniforms.to_vec();
+    for param in params {
+        if !uniforms.iter().any(|(name, _)| *name == param.name) {
+            uniforms.push((param.name.clone(), param.default));
+        }
+    }
+
+    uniforms
+}
+
+/// Names declared at module scope in `source`, with their line numbers. Only lines starting a
+/// `fn`, `struct`, `const`, `var`, `alias` or `override` outside of any braces are looked at,
+/// which covers the generated code and shaders written the usual way.
+pub fn declarations(source: &str) -> Vec<(usize, &str)> {
+    let mut depth = 0;
+    let mut names = Vec::new();
+
+    for (number, line) in source.lines().enumerate() {
+        let code = line.split("//").next().unwrap_or_default();
+        let mut words = code
+            .split_whitespace()
+            .skip_while(|word| word.starts_with('@'));
+        if let (0, Some(keyword), Some(name)) = (depth, words.next(), words.next()) {
+            let keyword = keyword.split('<').next().unwrap_or_default();
+            let end = name
+                .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+                .unwrap_or(name.len());
+            let declaration = matches!(
+                keyword,
+                "fn" | "struct" | "const" | "var" | "alias" | "override"
+            );
+            if declaration && end > 0 {
+                names.push((number + 1, &name[..end]));
+            }
+        }
+        depth += code.matches('{').count() as i32 - code.matches('}').count() as i32;
+    }
+
+    names
+}
+
+/// `line` with every identifier that is in `defines` replaced by its value.
+fn substitute(line: &str, defines: &[(String, String)]) -> String {
+    if defines.is_empty() {
+        return line.to_string();
+    }
+
+    let mut out = String::with_capacity(line.len());
+    let mut rest = line;
+    while let Some(start) = rest.find(|c: char| c.is_ascii_alphabetic() || c == '_') {
+        out.push_str(&rest[..start]);
+        rest = &rest[start..];
+        let end = rest
+            .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+            .unwrap_or(rest.len());
+        let word = &rest[..end];
+        match defines.iter().find(|(name, _)| name == word) {
+            Some((_, value)) => out.push_str(value),
+            None => out.push_str(word),
+        }
+        rest = &rest[end..];
+    }
+    out.push_str(rest);
+
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn defines(defines: &[(&str, &str)]) -> Vec<(String, String)> {
+        defines
+            .iter()
+            .map(|(name, value)| (name.to_string(), value.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn ifdefs_and_defines() {
+        // source, defines, and the output or None for an error
+        type Defines<'a> = &'a [(&'a str, &'a str)];
+        let cases: &[(&str, Defines, Option<&str>)] = &[
+            ("a\nb\n", &[], Some("a\nb\n")),
+            // whole identifiers only
+            (
+                "FOO FOOBAR _FOO FOO_ FOO.x",
+                &[("FOO", "2.0")],
+                Some("2.0 FOOBAR _FOO FOO_ 2.0.x\n"),
+            ),
+            (
+                "#ifdef A\nyes\n#else\nno\n#endif",
+                &[("A", "1")],
+                Some("\nyes\n\n\n\n"),
+            ),
+            (
+                "#ifdef A\nyes\n#else\nno\n#endif",
+                &[],
+                Some("\n\n\nno\n\n"),
+            ),
+            ("#ifndef A\nyes\n#endif", &[], Some("\nyes\n\n")),
+            (
+                "#ifdef A\n#ifdef B\nab\n#else\na\n#endif\n#else\nnone\n#endif",
+                &[("A", "1")],
+                Some("\n\n\n\na\n\n\n\n\n"),
+            ),
+            // an inner branch of a dropped one stays dropped, whatever its own condition
+            (
+                "#ifdef A\n#ifndef B\nin\n#endif\n#endif\nout",
+                &[],
+                Some("\n\n\n\n\nout\n"),
+            ),
+            ("#endif", &[], None),
+            ("#else", &[], None),
+            ("#ifdef A\nopen", &[], None),
+            ("#ifdef\n#endif", &[], None),
+            ("#include \"lib.wgsl\"", &[], None),
+        ];
+
+        for (source, defs, expected) in cases {
+            let result = apply_defines(source, &defines(defs)).ok();
+            assert_eq!(result.as_deref(), *expected, "{:?} with {:?}", source, defs);
+        }
+    }
+
+    #[test]
+    fn param_annotations() {
+        // name, min, max, default
+        type Declared<'a> = &'a [(&'a str, f32, f32, f32)];
+        let cases: &[(&str, Declared)] = &[
+            ("// @param speed 0 2 1", &[("speed", 0.0, 2.0, 1.0)]),
+            (
+                "  //@param   speed -1.5 2.5   0  ",
+                &[("speed", -1.5, 2.5, 0.0)],
+            ),
+            ("fn f() {} // @param speed 0 2 1", &[]),
+            ("@param speed 0 2 1", &[]),
+            // malformed ones are skipped, the rest still count
+            (
+                "// @param speed 0 2\n// @param zoom 1 4 2",
+                &[("zoom", 1.0, 4.0, 2.0)],
+            ),
+            ("// @param speed 0 2 1 3", &[]),
+            ("// @param speed zero 2 1", &[]),
+            ("// @param 2fast 0 2 1", &[]),
+            ("// @param speed 0 2 3", &[]),
+            ("// @param speed 2 0 1", &[]),
+            ("// @param speed 0 2 NaN", &[]),
+        ];
+
+        for (source, expected) in cases {
+            let params: Vec<_> = params(source)
+                .into_iter()
+                .map(|p| (p.name, p.min, p.max, p.default))
+                .collect();
+            let expected: Vec<_> = expected
+                .iter()
+                .map(|&(name, min, max, default)| (name.to_string(), min, max, default))
+                .collect();
+            assert_eq!(params, expected, "{:?}", source);
+        }
+    }
+
+    #[test]
+    fn params_keep_set_values() {
+        let params = params("// @param speed 0 2 1\n// @param zoom 1 4 2");
+        let uniforms = vec![("zoom".to_string(), 3.0), ("exposure".to_string(), 1.5)];
+        assert_eq!(
+            with_params(&uniforms, &params),
+            [
+                ("zoom".to_string(), 3.0),
+                ("exposure".to_string(), 1.5),
+                ("speed".to_string(), 1.0),
+            ]
+        );
+    }
+
+    #[test]
+    fn module_scope_declarations() {
+        let cases: &[(&str, &[(usize, &str)])] = &[
+            ("fn main_image() {}", &[(1, "main_image")]),
+            ("struct Uniforms {\n    time: f32,\n};", &[(1, "Uniforms")]),
+            (
+                "const PI: f32 = 3.14;\nalias v2 = vec2<f32>;",
+                &[(1, "PI"), (2, "v2")],
+            ),
+            (
+                "@group(0) @binding(0)\nvar<uniform> u: Uniforms;",
+                &[(2, "u")],
+            ),
+            ("@group(0) @binding(0) var t: texture_2d<f32>;", &[(1, "t")]),
+            ("override scale = 1.0;", &[(1, "scale")]),
+            // only module scope counts
+            (
+                "fn f() {\n    var x = 1;\n    let y = 2;\n}\nfn g() {}",
+                &[(1, "f"), (5, "g")],
+            ),
+            (
+                "fn f() {\n    if true {\n        var x = 1;\n    }\n}",
+                &[(1, "f")],
+            ),
+            // comments and other statements
+            ("// fn commented() {}\nlet x = 1;", &[]),
+            ("fn", &[]),
+            ("fn (", &[]),
+        ];
+
+        for (source, expected) in cases {
+            assert_eq!(declarations(source), *expected, "{:?}", source);
+        }
+    }
+
+    /// A directory of its own under the system temp dir, holding `files`.
+    fn shader_dir(name: &str, files: &[(&str, &str)]) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("glpaper-{}-{}", name, std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        for (file, contents) in files {
+            fs::write(dir.join(file), contents).unwrap();
+        }
+        dir
+    }
+
+    #[test]
+    fn includes() {
+        let dir = shader_dir(
+            "includes",
+            &[
+                ("main.wgsl", "#include \"lib.wgsl\"\nmain\n"),
+                ("lib.wgsl", "#ifdef FAST\nfast\n#else\nslow\n#endif\n"),
+                (
+                    "guarded.wgsl",
+                    "#ifdef EXTRA\n#include \"missing.wgsl\"\n#endif\nmain\n",
+                ),
+                ("self.wgsl", "#include \"self.wgsl\"\n"),
+            ],
+        );
+        let fast = [("FAST".to_string(), "1".to_string())];
+
+        let source = load_fragment_shader(Some(&dir.join("main.wgsl")), &fast).unwrap();
+        assert_eq!(source, "\nfast\n\n\n\nmain\n");
+        let source = load_fragment_shader(Some(&dir.join("main.wgsl")), &[]).unwrap();
+        assert_eq!(source, "\n\n\nslow\n\nmain\n");
+
+        // the missing file is only an error if its branch is kept
+        let source = load_fragment_shader(Some(&dir.join("guarded.wgsl")), &[]).unwrap();
+        assert_eq!(source, "\n\n\nmain\n");
+        let extra = [("EXTRA".to_string(), "1".to_string())];
+        assert!(load_fragment_shader(Some(&dir.join("guarded.wgsl")), &extra).is_err());
+
+        assert!(load_fragment_shader(Some(&dir.join("self.wgsl")), &[]).is_err());
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn compute_shaders() {
+        let dir = shader_dir(
+            "compute",
+            &[(
+                "noise.wgsl",
+                "#ifdef FAST
+fast
+#endif
+",
+            )],
+        );
+        let fast = [("FAST".to_string(), "1".to_string())];
+
+        let source = load_compute_shader(&dir.join("noise.wgsl"), &fast).unwrap();
+        assert_eq!(
+            source,
+            "
+fast
+
+"
+        );
+
+        // examples are fragment shaders
+        let e = load_compute_shader(Path::new("plasma"), &[]).unwrap_err();
+        assert_eq!(e.to_string(), "no compute shader file called plasma");
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
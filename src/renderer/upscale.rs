@@ -0,0 +1,323 @@
+use wgpu::{
+    BindGroup, BindGroupLayout, Color, CommandEncoder, Device, RenderPipeline, Sampler,
+    ShaderModule, Texture, TextureFormat, TextureView,
+};
+
+use crate::config::Transform;
+
+const UPSCALE: &str = include_str!("./assets/upscale.wgsl");
+
+/// Offscreen target for `--render-scale`, `--feedback`, `--pass-format`, `--transform` and
+/// `--aspect`. The shader draws into it at the scaled size and it is then stretched, turned as
+/// needed, over the surface, and with `--mirror` over the other outputs' surfaces too.
+pub struct Upscaler {
+    stretch: Stretch,
+    format: TextureFormat,
+    /// Of the surface.
+    size: (u32, u32),
+
+    module: ShaderModule,
+    pipeline: RenderPipeline,
+    bind_group_layout: BindGroupLayout,
+    sampler: Sampler,
+
+    texture: Texture,
+    view: TextureView,
+    bind_group: BindGroup,
+}
+
+/// How the frame the shader draws is fitted to the surface.
+#[derive(Clone, Copy, Debug)]
+pub struct Stretch {
+    /// `--render-scale`
+    pub scale: f32,
+    /// `--report-full-resolution`
+    pub full_resolution: bool,
+    pub transform: Transform,
+    /// `--upscale-filter`
+    pub filter: wgpu::FilterMode,
+    /// `--aspect`, width over height
+    pub aspect: Option<f32>,
+}
+
+impl Stretch {
+    /// `(width, height)` of the picture shown on a surface of `size`, before `scale`.
+    pub fn picture_size(&self, size: (u32, u32)) -> (u32, u32) {
+        fit_aspect(self.transform.picture_size(size), self.aspect)
+    }
+}
+
+/// The largest part of `size` that is `aspect` wide over high, or all of it without one.
+pub fn fit_aspect((width, height): (u32, u32), aspect: Option<f32>) -> (u32, u32) {
+    match aspect {
+        Some(aspect) if width as f32 > height as f32 * aspect => {
+            (((height as f32 * aspect).round() as u32).max(1), height)
+        }
+        Some(aspect) => (width, ((width as f32 / aspect).round() as u32).max(1)),
+        None => (width, height),
+    }
+}
+
+impl Upscaler {
+    /// `format` is what the shader draws in and `target_format` is the surface's.
+    pub fn new(
+        device: &Device,
+        format: TextureFormat,
+        target_format: TextureFormat,
+        size: (u32, u32),
+        stretch: Stretch,
+    ) -> Self {
+        let source = format!(
+            "const TRANSFORM: u32 = {}u;\n\n{}",
+            stretch.transform as u32, UPSCALE
+        );
+        let module = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("upscale_shader"),
+            source: wgpu::ShaderSource::Wgsl(source.into()),
+        });
+
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Upscale Bind Group Layout"),
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        multisampled: false,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                    count: None,
+                },
+            ],
+        });
+
+        let pipeline = create_pipeline(device, &module, &bind_group_layout, target_format);
+
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some("Upscale Sampler"),
+            mag_filter: stretch.filter,
+            min_filter: stretch.filter,
+            ..Default::default()
+        });
+
+        let (texture, view, bind_group) = create_target(
+            device,
+            &bind_group_layout,
+            &sampler,
+            format,
+            scaled(stretch.picture_size(size), stretch.scale),
+        );
+
+        Self {
+            stretch,
+            format,
+            size,
+            module,
+            pipeline,
+            bind_group_layout,
+            sampler,
+            texture,
+            view,
+            bind_group,
+        }
+    }
+
+    /// The texture behind [`Self::view`].
+    pub fn texture(&self) -> &Texture {
+        &self.texture
+    }
+
+    /// Where the shader should draw.
+    pub fn view(&self) -> &TextureView {
+        &self.view
+    }
+
+    /// The size the shader is drawn at for a surface of `size`.
+    pub fn render_size(&self, size: (u32, u32)) -> (u32, u32) {
+        scaled(self.stretch.picture_size(size), self.stretch.scale)
+    }
+
+    /// What `resolution` should report for a surface of `size`.
+    pub fn resolution(&self, size: (u32, u32)) -> (u32, u32) {
+        if self.stretch.full_resolution {
+            self.stretch.picture_size(size)
+        } else {
+            self.render_size(size)
+        }
+    }
+
+    pub fn resize(&mut self, device: &Device, width: u32, height: u32) {
+        let (texture, view, bind_group) = create_target(
+            device,
+            &self.bind_group_layout,
+            &self.sampler,
+            self.format,
+            self.render_size((width, height)),
+        );
+        self.size = (width, height);
+        self.texture = texture;
+        self.view = view;
+        self.bind_group = bind_group;
+    }
+
+    /// Stretches what was drawn into `view` over `target`, filling any `--aspect` bars with
+    /// `clear_color`.
+    pub fn draw(&self, encoder: &mut CommandEncoder, target: &TextureView, clear_color: Color) {
+        let viewport = self.viewport(self.size, self.stretch.aspect);
+        self.draw_pass(encoder, &self.pipeline, target, viewport, clear_color);
+    }
+
+    /// A pipeline for [`Self::draw_mirror`] onto surfaces of `format`. Only works with this
+    /// upscaler.
+    pub fn mirror_pipeline(&self, device: &Device, format: TextureFormat) -> RenderPipeline {
+        create_pipeline(device, &self.module, &self.bind_group_layout, format)
+    }
+
+    /// Stretches what was drawn into `view` over `target`, another output's surface of `size`,
+    /// keeping the picture's shape and filling the bars around it with `clear_color`.
+    pub fn draw_mirror(
+        &self,
+        encoder: &mut CommandEncoder,
+        pipeline: &RenderPipeline,
+        target: &TextureView,
+        size: (u32, u32),
+        clear_color: Color,
+    ) {
+        let (width, height) = self.stretch.picture_size(self.size);
+        let viewport = self.viewport(size, Some(width as f32 / height as f32));
+        self.draw_pass(encoder, pipeline, target, viewport, clear_color);
+    }
+
+    /// `(x, y, width, height)` of the picture centered on a surface of `size`, `aspect` wide over
+    /// high if given, and turned back the way it's shown.
+    fn viewport(&self, size: (u32, u32), aspect: Option<f32>) -> (u32, u32, u32, u32) {
+        let transform = self.stretch.transform;
+        let (width, height) =
+            transform.picture_size(fit_aspect(transform.picture_size(size), aspect));
+        let x = size.0.saturating_sub(width) / 2;
+        let y = size.1.saturating_sub(height) / 2;
+        (x, y, width, height)
+    }
+
+    fn draw_pass(
+        &self,
+        encoder: &mut CommandEncoder,
+        pipeline: &RenderPipeline,
+        target: &TextureView,
+        (x, y, width, height): (u32, u32, u32, u32),
+        clear_color: Color,
+    ) {
+        let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("Upscale Pass"),
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view: target,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Clear(clear_color),
+                    store: true,
+                },
+            })],
+            depth_stencil_attachment: None,
+        });
+
+        render_pass.set_viewport(x as f32, y as f32, width as f32, height as f32, 0.0, 1.0);
+
+        render_pass.set_pipeline(pipeline);
+        render_pass.set_bind_group(0, &self.bind_group, &[]);
+        render_pass.draw(0..3, 0..1);
+    }
+}
+
+fn create_pipeline(
+    device: &Device,
+    module: &ShaderModule,
+    layout: &BindGroupLayout,
+    target_format: TextureFormat,
+) -> RenderPipeline {
+    let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+        label: None,
+        bind_group_layouts: &[layout],
+        push_constant_ranges: &[],
+    });
+
+    device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+        label: Some("Upscale Pipeline"),
+        layout: Some(&pipeline_layout),
+        vertex: wgpu::VertexState {
+            module,
+            entry_point: "vs_main",
+            buffers: &[],
+        },
+        fragment: Some(wgpu::FragmentState {
+            module,
+            entry_point: "fs_main",
+            targets: &[Some(wgpu::ColorTargetState {
+                format: target_format,
+                blend: None,
+                write_mask: wgpu::ColorWrites::ALL,
+            })],
+        }),
+        primitive: wgpu::PrimitiveState::default(),
+        depth_stencil: None,
+        multisample: wgpu::MultisampleState::default(),
+        multiview: None,
+    })
+}
+
+fn scaled((width, height): (u32, u32), scale: f32) -> (u32, u32) {
+    (
+        ((width as f32 * scale).round() as u32).max(1),
+        ((height as f32 * scale).round() as u32).max(1),
+    )
+}
+
+fn create_target(
+    device: &Device,
+    layout: &BindGroupLayout,
+    sampler: &Sampler,
+    format: TextureFormat,
+    (width, height): (u32, u32),
+) -> (Texture, TextureView, BindGroup) {
+    let texture = device.create_texture(&wgpu::TextureDescriptor {
+        label: Some("Upscale Source"),
+        size: wgpu::Extent3d {
+            width,
+            height,
+            depth_or_array_layers: 1,
+        },
+        mip_level_count: 1,
+        sample_count: 1,
+        dimension: wgpu::TextureDimension::D2,
+        format,
+        // copied from for --feedback
+        usage: wgpu::TextureUsages::RENDER_ATTACHMENT
+            | wgpu::TextureUsages::TEXTURE_BINDING
+            | wgpu::TextureUsages::COPY_SRC,
+        view_formats: &[],
+    });
+    let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+
+    let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+        label: Some("Upscale Bind Group"),
+        layout,
+        entries: &[
+            wgpu::BindGroupEntry {
+                binding: 0,
+                resource: wgpu::BindingResource::TextureView(&view),
+            },
+            wgpu::BindGroupEntry {
+                binding: 1,
+                resource: wgpu::BindingResource::Sampler(sampler),
+            },
+        ],
+    });
+
+    (texture, view, bind_group)
+}
@@ -0,0 +1,163 @@
+use std::path::Path;
+
+use anyhow::{Context, Result};
+
+use super::{
+    adapter,
+    feedback::Feedback,
+    output_surface::{self, PipelineSetup},
+    precompute::Precompute,
+    provider,
+    renderable::{self, Inputs, RenderConfig, RenderState},
+    shader,
+};
+use crate::config::{Options, OutputSettings, Overlay};
+
+/// Compiles the shader at `path` and its `--overlay`s and builds pipelines for them on an
+/// offscreen device, the same way an output would, without connecting to Wayland.
+pub fn validate(path: &Path, options: &Options) -> Result<()> {
+    let settings = options.for_output(None);
+    let shader_source = shader::load_fragment_shader(Some(path), &settings.defines)?;
+    let overlay_sources = settings
+        .overlays
+        .iter()
+        .map(|overlay| {
+            let source = shader::load_fragment_shader(Some(&overlay.shader), &settings.defines)?;
+            Ok((overlay.clone(), source))
+        })
+        .collect::<Result<Vec<_>>>()?;
+    let mut uniforms = settings.uniforms.clone();
+    for &kind in &settings.providers {
+        uniforms.extend(provider::uniforms(provider::create(kind).as_mut()));
+    }
+    let params: Vec<_> = std::iter::once(&shader_source)
+        .chain(overlay_sources.iter().map(|(_, source)| source))
+        .flat_map(|source| shader::params(source))
+        .collect();
+    let uniforms = shader::with_params(&uniforms, &params);
+
+    let instance = wgpu::Instance::new(wgpu::InstanceDescriptor {
+        backends: settings.adapter.backends,
+        ..Default::default()
+    });
+    let adapter = adapter::select_adapter(&instance, None, &settings.adapter)?;
+    let (device, _queue) = pollster::block_on(adapter.request_device(&Default::default(), None))
+        .context("couldnt get device")?;
+
+    // checked first, the shader can only read `precomputed` when there is a compute shader
+    let precompute = match settings.compute {
+        Some(ref compute_path) => {
+            let source = shader::load_compute_shader(compute_path, &settings.defines)?;
+            let precompute =
+                output_surface::catch_errors(&device, || Precompute::new(&device, &source, (1, 1)))
+                    .with_context(|| format!("{} is invalid", compute_path.display()))?;
+            Some(precompute)
+        }
+        None => None,
+    };
+
+    // whether the shader encodes sRGB itself depends on the surface, which is up to the driver
+    for swapchain_format in [
+        wgpu::TextureFormat::Bgra8UnormSrgb,
+        wgpu::TextureFormat::Bgra8Unorm,
+    ] {
+        let shaders = Shaders {
+            path,
+            source: &shader_source,
+            overlay_sources: &overlay_sources,
+            uniforms: &uniforms,
+        };
+        build_pipelines(
+            &device,
+            &settings,
+            &shaders,
+            precompute.as_ref(),
+            swapchain_format,
+        )
+        .with_context(|| format!("checking for a {:?} surface", swapchain_format))?;
+    }
+
+    Ok(())
+}
+
+/// The shaders being checked, and the uniforms they're built with.
+struct Shaders<'a> {
+    path: &'a Path,
+    source: &'a str,
+    overlay_sources: &'a [(Overlay, String)],
+    uniforms: &'a [(String, f32)],
+}
+
+/// Builds the pipelines for `shaders` like an output drawing to a surface of `swapchain_format`.
+fn build_pipelines(
+    device: &wgpu::Device,
+    settings: &OutputSettings,
+    shaders: &Shaders,
+    precompute: Option<&Precompute>,
+    swapchain_format: wgpu::TextureFormat,
+) -> Result<()> {
+    let format = settings.pass_format.unwrap_or(swapchain_format);
+    let encode_srgb = !swapchain_format.describe().srgb;
+    let inputs = Inputs {
+        feedback: settings.feedback,
+        precomputed: precompute.is_some(),
+    };
+
+    let render_state = RenderState::new(
+        device,
+        (1, 1),
+        shaders.uniforms,
+        1.0,
+        None,
+        settings.placement,
+    );
+    let feedback = settings
+        .feedback
+        .then(|| Feedback::new(device, format, (1, 1)));
+    let mut bind_group_layouts = vec![&render_state.uniform_bind_group_layout];
+    bind_group_layouts.extend(feedback.as_ref().map(|f| f.bind_group_layout()));
+    bind_group_layouts.extend(precompute.map(|p| p.bind_group_layout()));
+
+    output_surface::catch_errors(device, || -> Result<()> {
+        let config = RenderConfig::new(
+            device,
+            shaders.source,
+            shaders.uniforms,
+            settings.clear_color.a < 1.0,
+            1.0,
+            inputs,
+            encode_srgb,
+        )?;
+        renderable::create_pipeline(
+            device,
+            &config,
+            &bind_group_layouts,
+            format,
+            1,
+            wgpu::BlendState::ALPHA_BLENDING,
+        );
+        Ok(())
+    })
+    .and_then(|result| result)
+    .with_context(|| format!("{} is invalid", shaders.path.display()))?;
+
+    let setup = PipelineSetup {
+        coord_scale: 1.0,
+        inputs,
+        encode_srgb,
+        bind_group_layouts: &bind_group_layouts,
+        format,
+        sample_count: 1,
+    };
+    let pipelines = output_surface::overlay_pipelines(
+        device,
+        shaders.overlay_sources,
+        shaders.uniforms,
+        &setup,
+    );
+    for ((overlay, _), pipeline) in shaders.overlay_sources.iter().zip(pipelines) {
+        pipeline.with_context(|| format!("overlay {} is invalid", overlay.shader.display()))?;
+    }
+
+    Ok(())
+}